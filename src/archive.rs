@@ -0,0 +1,422 @@
+use crate::comic::{self, ComicPage, COMIC_INFO_NAME};
+use crate::interrupt::{self, PartialFile};
+use crate::metadata;
+use crate::JxlArgs;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArchiveKind {
+    Zip,
+    //만화책용 zip. 페이지를 사람이 읽는 순서로 넣고 ComicInfo.xml 을 추가함.
+    Cbz,
+}
+
+impl ArchiveKind {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveKind::Zip => "zip",
+            ArchiveKind::Cbz => "cbz",
+        }
+    }
+}
+
+//폴더 이름 뒤에 확장자를 붙인 zip 경로. Vol.1 은 Vol.1.zip 이 됨.
+//같은 이름의 zip 이 이미 있으면 덮어쓰지 않도록 Vol.1(1).zip 처럼 번호를 붙임.
+pub fn archive_path(folder_path: &Path, archive_kind: ArchiveKind) -> PathBuf {
+    let folder_name = folder_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut zip_path =
+        folder_path.with_file_name(format!("{}.{}", folder_name, archive_kind.extension()));
+    let mut number = 0;
+    while zip_path.exists() {
+        number += 1;
+        zip_path = folder_path.with_file_name(format!(
+            "{}({}).{}",
+            folder_name,
+            number,
+            archive_kind.extension()
+        ));
+    }
+    zip_path
+}
+
+//쓰는 중인 zip 의 이름. {zip}.part
+fn part_path(zip_path: &Path) -> PathBuf {
+    let mut part_path = zip_path.as_os_str().to_owned();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+//make_zip 이 만든 zip 과, 거기에 들어간 원본 이미지.
+pub struct MadeZip {
+    pub path: PathBuf,
+    pub source_files: Vec<PathBuf>,
+}
+
+//zip 을 임시 이름으로 다 쓰고, fsync, 다시 열어서 확인한 뒤 이름을 바꿈.
+//jxl 파일은 zip 이 제자리에 들어간 다음에만 지움. 중간에 꺼져도 jxl 이나 예전 zip 은 남아있음.
+//source_files 는 jxl 대신 넣은 원본 이미지. 여기서 지우지 않고 zip 에 들어간 것만 돌려줌.
+//원본은 delete_source_image, dont_use_trashcan_just_delete 에 따라 부르는 쪽이 지움.
+//replace 는 다시 변환한 폴더로 예전에 만든 zip. 번호를 붙이지 않고 다 쓴 zip 으로 바꿔 넣음.
+//만든 zip 의 경로를 돌려줌. 넣을 파일이 없으면 None.
+pub fn make_zip(
+    folder_path: &Path,
+    zip_options: FileOptions,
+    mut pack_files_list: Vec<PathBuf>,
+    source_files: &[PathBuf],
+    archive_kind: ArchiveKind,
+    replace: Option<&Path>,
+) -> Result<Option<MadeZip>, Box<dyn Error>> {
+    //zip 과 cbz 가 바뀌었으면 예전 zip 은 두고 새로 만듦.
+    let zip_path = match replace {
+        Some(replace)
+            if replace.is_file()
+                && replace.extension() == Some(OsStr::new(archive_kind.extension())) =>
+        {
+            replace.to_path_buf()
+        }
+        _ => archive_path(folder_path, archive_kind),
+    };
+    let temp_path = part_path(&zip_path);
+
+    //이어서 변환한 jxl 은 폴더에 있던 jxl 로도 한 번 더 잡힘.
+    let mut seen = HashSet::new();
+    pack_files_list.retain(|pack_file| seen.insert(pack_file.clone()));
+
+    if archive_kind == ArchiveKind::Cbz {
+        //만화 뷰어는 zip 에 들어간 순서대로 보여주는 경우가 많음.
+        pack_files_list.sort_by(|a, b| {
+            comic::natural_cmp(&entry_name(folder_path, a), &entry_name(folder_path, b))
+        });
+    }
+
+    let mut packed_files = vec![];
+    let partial = PartialFile::new(&temp_path);
+    let result = write_zip(
+        folder_path,
+        &temp_path,
+        zip_options,
+        &pack_files_list,
+        &mut packed_files,
+        archive_kind,
+    )
+    //다시 열어서 넣을 때 기록한 크기, CRC32, xxh3 와 비교.
+    .and_then(|packed_entries| check_packed_zip(&temp_path, &packed_entries));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+
+    //생성한 zip file이 비었다면 만들지 않음.
+    if packed_files.is_empty() {
+        fs::remove_file(&temp_path)?;
+        return Ok(None);
+    }
+
+    //같은 이름의 예전 zip 은 이름을 바꾸면서 한 번에 바뀜.
+    fs::rename(&temp_path, &zip_path)?;
+    drop(partial);
+    sync_parent_dir(&zip_path)?;
+    info!("zip created: {}", zip_path.display());
+
+    let mut packed_sources = vec![];
+    for pack_file in packed_files {
+        if source_files.contains(pack_file) {
+            packed_sources.push(pack_file.clone());
+        } else {
+            fs::remove_file(pack_file)?; //zip 파일에 추가한 jxl 파일을 삭제.
+        }
+    }
+    Ok(Some(MadeZip {
+        path: zip_path,
+        source_files: packed_sources,
+    }))
+}
+
+//zip 에 넣을 때 기록한 값.
+pub struct PackedEntry {
+    pub name: String,
+    pub size: u64,
+    pub crc32: u32,
+    pub xxh3: u64,
+}
+
+impl PackedEntry {
+    pub fn new(name: String, data: &[u8]) -> Self {
+        PackedEntry {
+            name,
+            size: data.len() as u64,
+            crc32: crc32fast::hash(data),
+            xxh3: xxh3_64(data),
+        }
+    }
+}
+
+fn write_zip<'a>(
+    folder_path: &Path,
+    temp_path: &Path,
+    zip_options: FileOptions,
+    pack_files_list: &'a [PathBuf],
+    packed_files: &mut Vec<&'a PathBuf>,
+    archive_kind: ArchiveKind,
+) -> Result<Vec<PackedEntry>, Box<dyn Error>> {
+    let mut zip = ZipWriter::new(File::create(temp_path)?);
+    let mut packed_entries = vec![];
+    let mut comic_pages = vec![];
+
+    for pack_file in pack_files_list {
+        //Ctrl-C 면 쓰다 만 zip 은 make_zip 이 지움. jxl 은 그대로 남음.
+        if interrupt::interrupted() {
+            return Err("interrupted".into());
+        }
+        let mut file = match File::open(pack_file) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                warn!(
+                    "aleady delete. maybe duplicate file: {}",
+                    pack_file.display()
+                );
+                continue;
+            }
+            Err(err) => {
+                warn!("Failed to open file: {}", pack_file.display());
+                return Err(err.into());
+            }
+        };
+
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer)?;
+        let file_metadata = file.metadata()?;
+        let name = entry_name(folder_path, pack_file);
+        let packed = PackedEntry::new(name, &buffer);
+        start_file_with_times(
+            &mut zip,
+            &packed.name,
+            zip_options,
+            Some(file_metadata.modified()?),
+            file_metadata.accessed().ok(),
+            packed.xxh3,
+        )?;
+        zip.write_all(&buffer)?;
+        packed_entries.push(packed);
+        if archive_kind == ArchiveKind::Cbz {
+            comic_pages.push(ComicPage {
+                size: buffer.len() as u64,
+                dimensions: comic::page_dimensions(&buffer),
+            });
+        }
+        packed_files.push(pack_file);
+    }
+
+    if archive_kind == ArchiveKind::Cbz && !comic_pages.is_empty() {
+        //제목은 폴더 이름.
+        let title = folder_path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let comic_info = comic::comic_info_xml(&title, &comic_pages);
+        let packed = PackedEntry::new(COMIC_INFO_NAME.to_string(), comic_info.as_bytes());
+        start_file_with_times(
+            &mut zip,
+            COMIC_INFO_NAME,
+            zip_options,
+            Some(SystemTime::now()),
+            None,
+            packed.xxh3,
+        )?;
+        zip.write_all(comic_info.as_bytes())?;
+        packed_entries.push(packed);
+    }
+    let file = zip.finish()?;
+    file.sync_all()?;
+    Ok(packed_entries)
+}
+
+//zip 항목의 수정 시간을 파일의 시간으로 씀. MS-DOS 시간과 함께 extended timestamp 도 넣음.
+//modified 가 None 이면 zip_options 의 시간을 그대로 씀.
+//넣는 데이터의 xxh3 는 중앙 디렉터리에 남겨서, verify 가 나중에 CRC32 와 함께 비교함.
+pub fn start_file_with_times(
+    zip: &mut ZipWriter<File>,
+    name: &str,
+    zip_options: FileOptions,
+    modified: Option<SystemTime>,
+    accessed: Option<SystemTime>,
+    xxh3: u64,
+) -> Result<(), Box<dyn Error>> {
+    let zip_options = match modified.and_then(metadata::zip_date_time) {
+        Some(date_time) => zip_options.last_modified_time(date_time),
+        None => zip_options,
+    };
+    zip.start_file_with_extra_data(name, zip_options)?;
+    if let Some(modified) = modified {
+        zip.write_all(&metadata::extended_timestamp(modified, accessed, true))?;
+    }
+    zip.end_local_start_central_extra_data()?;
+    if let Some(modified) = modified {
+        zip.write_all(&metadata::extended_timestamp(modified, accessed, false))?;
+    }
+    zip.write_all(&XXH3_EXTRA_ID.to_le_bytes())?;
+    zip.write_all(&8u16.to_le_bytes())?;
+    zip.write_all(&xxh3.to_le_bytes())?;
+    zip.end_extra_data()?;
+    Ok(())
+}
+
+//이 프로그램이 중앙 디렉터리에 넣는 xxh3 extra field 의 id. "xh"
+const XXH3_EXTRA_ID: u16 = 0x6878;
+
+//중앙 디렉터리의 extra field 에서 넣을 때 기록한 xxh3 를 찾음. 다른 프로그램이 만든 zip 은 없음.
+fn recorded_xxh3(extra_data: &[u8]) -> Option<u64> {
+    metadata::zip_extra_field(extra_data, XXH3_EXTRA_ID)
+        .and_then(|field| field.try_into().ok())
+        .map(u64::from_le_bytes)
+}
+
+//zip 안의 이름은 folder_path 에서의 상대 경로. 하위 폴더는 "disc1/01.jxl" 처럼 들어감.
+pub fn entry_name(folder_path: &Path, pack_file: &Path) -> String {
+    match pack_file.strip_prefix(folder_path) {
+        Ok(relative) => relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => pack_file
+            .file_name()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+//다 쓴 zip 을 다시 열어서 모든 파일이 기록한 값과 같은지 확인.
+pub fn check_packed_zip(
+    zip_path: &Path,
+    packed_entries: &[PackedEntry],
+) -> Result<(), Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(zip_path)?)?;
+    if archive.len() != packed_entries.len() {
+        return Err(format!(
+            "zip has {} files but {} files were packed",
+            archive.len(),
+            packed_entries.len()
+        )
+        .into());
+    }
+
+    let mut buffer = vec![0; 64 * 1024];
+    for packed in packed_entries {
+        let mut entry = archive.by_name(&packed.name)?;
+        if entry.size() != packed.size || entry.crc32() != packed.crc32 {
+            return Err(format!("zip entry header is different: {}", packed.name).into());
+        }
+        let mut hasher = Xxh3::new();
+        loop {
+            let read = entry.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        if hasher.digest() != packed.xxh3 {
+            return Err(format!("zip entry is different from packed file: {}", packed.name).into());
+        }
+    }
+    Ok(())
+}
+
+//rename 한 결과가 디스크에 남도록 폴더도 fsync. 윈도우는 폴더를 열 수 없으므로 건너뜀.
+#[cfg(unix)]
+pub fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+//zip 파일을 끝까지 읽어서, 중앙 디렉터리에 기록된 크기와 CRC32 를 다시 계산한 값과 비교함.
+//넣을 때 기록한 xxh3 가 있으면 그것도 비교함. 만든 뒤 오래 보관한 디스크에서 비트 손상을 찾는 용도.
+//확인한 파일 수와, 그 중 xxh3 까지 확인한 파일 수를 돌려줌.
+pub fn check_zip(zip_path: &Path) -> Result<(usize, usize), Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(zip_path)?)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut xxh3_checked = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let recorded = recorded_xxh3(entry.extra_data());
+        let mut hasher = crc32fast::Hasher::new();
+        let mut xxh3 = Xxh3::new();
+        let mut size = 0;
+        loop {
+            let read = entry.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            if recorded.is_some() {
+                xxh3.update(&buffer[..read]);
+            }
+            size += read as u64;
+        }
+        if size != entry.size() || hasher.finalize() != entry.crc32() {
+            return Err(format!("CRC32 mismatch: {}", entry.name()).into());
+        }
+        if let Some(recorded) = recorded {
+            if xxh3.digest() != recorded {
+                return Err(format!("xxh3 mismatch: {}", entry.name()).into());
+            }
+            xxh3_checked += 1;
+        }
+    }
+    Ok((archive.len(), xxh3_checked))
+}
+
+//scan_folders 로 찾은 폴더마다 바로 아래의 zip 을 그 폴더의 설정과 함께 돌려줌. cbz 도 zip 이므로 함께 찾음.
+//건너뛴 폴더의 zip 과 exclude_files 에 맞는 zip 은 빼고 돌려줌.
+pub fn find_zip_files(
+    work_folder: &Path,
+    folder_list: &[(PathBuf, Arc<JxlArgs>)],
+) -> Vec<(PathBuf, Arc<JxlArgs>)> {
+    let mut zip_files = vec![];
+    for (folder_path, folder_args) in folder_list {
+        let Ok(read_dir) = folder_path.read_dir() else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = read_dir
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path.extension().and_then(OsStr::to_str).is_some_and(|ext| {
+                        ext.eq_ignore_ascii_case("zip") || ext.eq_ignore_ascii_case("cbz")
+                    })
+            })
+            .collect();
+        paths.sort();
+        for path in paths {
+            if let Some(reason) = folder_args.filter.skip_archive(work_folder, &path) {
+                info!("Skip {} ({})", path.display(), reason);
+                continue;
+            }
+            zip_files.push((path, folder_args.clone()));
+        }
+    }
+    zip_files
+}
@@ -0,0 +1,338 @@
+use crate::encoder::EncoderKind;
+use crate::filter::Pattern;
+use crate::format::SourceFormat;
+use crate::{JxlArgs, SizePolicy};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Convert PNG and JPG files to JXL and pack them into ZIP files"
+)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    //서브커맨드 없이 폴더만 주면 convert로 동작함. (드래그 앤 드롭 호환)
+    #[command(flatten)]
+    pub work: WorkArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert images to JXL, then pack each folder into ZIP if make_zip is set
+    Convert(WorkArgs),
+    /// Pack JXL files already in the folders into ZIP without encoding anything
+    Zip(WorkArgs),
+    /// Check that every ZIP under the folders can be read back. Every file is compared with its
+    /// CRC32, and with the xxh3 recorded while packing when the ZIP was made by this program
+    Verify(WorkArgs),
+    /// Extract every ZIP under the folders back into a folder and decode the JXL files back to
+    /// the original JPEG (bit exact, when the JXL has JPEG reconstruction data) or to PNG
+    Unpack(WorkArgs),
+    /// Convert the images inside existing ZIP and CBZ files to JXL, keep the other files as they
+    /// are, and replace each archive after checking it
+    Repack(WorkArgs),
+    /// Show the saved worklist of the folders
+    Status(WorkArgs),
+}
+
+impl Cli {
+    pub fn into_command(self) -> (CommandKind, WorkArgs) {
+        match self.command {
+            Some(Command::Convert(work)) => (CommandKind::Convert, work),
+            Some(Command::Zip(work)) => (CommandKind::Zip, work),
+            Some(Command::Verify(work)) => (CommandKind::Verify, work),
+            Some(Command::Unpack(work)) => (CommandKind::Unpack, work),
+            Some(Command::Repack(work)) => (CommandKind::Repack, work),
+            Some(Command::Status(work)) => (CommandKind::Status, work),
+            None => (CommandKind::Convert, self.work),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommandKind {
+    Convert,
+    Zip,
+    Verify,
+    Unpack,
+    Repack,
+    Status,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct WorkArgs {
+    /// Work folders to process. Several folders can be given at once
+    #[arg(value_name = "WORK_FOLDER")]
+    pub folders: Vec<PathBuf>,
+
+    /// Never read stdin: no folder prompt and no "Press Enter to exit"
+    #[arg(long)]
+    pub non_interactive: bool,
+
+    /// Convert again the folders that were made with other png_args, jpg_args, ... or another cjxl
+    /// version. Only folders whose source images are still there can be converted again
+    #[arg(long)]
+    pub reencode_stale: bool,
+
+    /// Only print what convert or zip would do: conversions with their args, archives with
+    /// their files, and everything that would be deleted or trashed. Nothing is written
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Write the dry run plan as JSON to this file. Implies --dry-run
+    #[arg(long, value_name = "FILE")]
+    pub plan_json: Option<PathBuf>,
+
+    /// Write a report of convert with one record per source image and the bytes saved per folder
+    /// and for the whole run. FILE.csv is written as CSV, anything else as JSON Lines. Can be
+    /// given more than once
+    #[arg(long, value_name = "FILE")]
+    pub report: Vec<PathBuf>,
+
+    /// Read this TOML config after the user config and every .jxlzip.toml, so only command line
+    /// options override it
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Apply this profile of the configs, e.g. archival, fast, lossy-web
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    #[command(flatten)]
+    pub overrides: JxlArgsOverride,
+}
+
+//설정 파일의 값을 덮어쓰는 옵션들.
+#[derive(Args, Clone, Default)]
+pub struct JxlArgsOverride {
+    /// Override delete_folder
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub delete_folder: Option<bool>,
+
+    /// Override delete_source_image
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub delete_source_image: Option<bool>,
+
+    /// Override make_zip
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub make_zip: Option<bool>,
+
+    /// Override dont_use_trashcan_just_delete
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub dont_use_trashcan_just_delete: Option<bool>,
+
+    /// Override verify_image. Decode every JXL and compare it with the source before deleting anything
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub verify_image: Option<bool>,
+
+    /// Override args.png, comma separated. e.g. --png-args=--distance=0,--effort=7
+    #[arg(
+        long,
+        value_name = "ARGS",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub png_args: Option<Vec<String>>,
+
+    /// Override args.jpg, comma separated. e.g. --jpg-args=--distance=0,--lossless_jpeg=1
+    #[arg(
+        long,
+        value_name = "ARGS",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub jpg_args: Option<Vec<String>>,
+
+    /// Override args of one format, FORMAT:ARGS. Can be repeated.
+    /// FORMAT is one of jpg, png, gif, webp, bmp, tiff, pnm, qoi, exr.
+    /// e.g. --format-args=webp:--distance=0,--effort=9
+    #[arg(long, value_name = "FORMAT:ARGS", allow_hyphen_values = true, value_parser = parse_format_args)]
+    pub format_args: Vec<(SourceFormat, Vec<String>)>,
+
+    /// Override zip_depth. Make one ZIP per folder at this depth below the work folder (0 is the
+    /// work folder itself) with nested images stored by relative path
+    #[arg(long, value_name = "DEPTH")]
+    pub zip_depth: Option<usize>,
+
+    /// Override size_policy. jxl keeps the JXL, source keeps the original without converting,
+    /// smaller keeps the JXL only when it is at least min_savings percent smaller
+    #[arg(long, value_enum)]
+    pub size_policy: Option<SizePolicy>,
+
+    /// Override min_savings, in percent. Used with --size-policy=smaller
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub min_savings: Option<f64>,
+
+    /// Override comic. Make CBZ with natural sorted pages and ComicInfo.xml instead of ZIP
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub comic: Option<bool>,
+
+    /// Make CBZ for folders whose name matches this pattern, * and ? allowed. Can be repeated.
+    /// Added to comic_folders. e.g. --comic-folder=*manga*
+    #[arg(long, value_name = "PATTERN")]
+    pub comic_folder: Vec<String>,
+
+    /// Path of the cjxl encoder. Overrides cjxl_path and the CJXL_PATH environment variable
+    #[arg(long, value_name = "PATH")]
+    pub cjxl: Option<PathBuf>,
+
+    /// Override encoder. libjxl needs a build with `--features libjxl`
+    #[arg(long, value_enum)]
+    pub encoder: Option<EncoderKind>,
+
+    /// Override max_encodes. Run at most this many encoders at once
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_encodes: Option<usize>,
+
+    /// Override memory_budget, in MB. Images whose estimated encoder memory would go over the
+    /// budget wait until running encodes finish
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub memory_budget: Option<u64>,
+
+    /// Override low_priority. Run this program and the encoders at lower CPU priority
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub low_priority: Option<bool>,
+
+    /// Convert only folders matching this pattern and the folders below them. Can be repeated.
+    /// Added to include_folders. e.g. --include-folder=**/DCIM/**
+    #[arg(long, value_name = "PATTERN", value_parser = Pattern::new)]
+    pub include_folder: Vec<Pattern>,
+
+    /// Do not read folders matching this pattern. Can be repeated. Added to exclude_folders
+    #[arg(long, value_name = "PATTERN", value_parser = Pattern::new)]
+    pub exclude_folder: Vec<Pattern>,
+
+    /// Convert only images matching this pattern. Can be repeated. Added to include_files
+    #[arg(long, value_name = "PATTERN", value_parser = Pattern::new)]
+    pub include_file: Vec<Pattern>,
+
+    /// Do not convert images matching this pattern. Can be repeated. Added to exclude_files.
+    /// e.g. --exclude-file=re:_thumb\.png$
+    #[arg(long, value_name = "PATTERN", value_parser = Pattern::new)]
+    pub exclude_file: Vec<Pattern>,
+
+    /// Override min_bytes. Skip images smaller than this many bytes, 0 converts all
+    #[arg(long, value_name = "BYTES")]
+    pub min_bytes: Option<u64>,
+
+    /// Override max_pixels. Skip images with more pixels (width x height) than this, 0 is no limit
+    #[arg(long, value_name = "PIXELS")]
+    pub max_pixels: Option<u64>,
+
+    /// Override min_age_days. Skip images modified in the last DAYS days, 0 converts all
+    #[arg(long, value_name = "DAYS")]
+    pub min_age_days: Option<u64>,
+}
+
+impl JxlArgsOverride {
+    pub fn apply(&self, jxl_args: &mut JxlArgs) {
+        if let Some(value) = self.delete_folder {
+            jxl_args.delete_folder_plag = value;
+        }
+        if let Some(value) = self.delete_source_image {
+            jxl_args.delete_source_image_plag = value;
+        }
+        if let Some(value) = self.make_zip {
+            jxl_args.make_zip_plag = value;
+        }
+        if let Some(value) = self.dont_use_trashcan_just_delete {
+            jxl_args.dont_use_trashcan_just_delete = value;
+        }
+        if let Some(value) = self.verify_image {
+            jxl_args.verify_image = value;
+        }
+        if let Some(args) = &self.png_args {
+            jxl_args.format_args.insert(SourceFormat::Png, args.clone());
+        }
+        if let Some(args) = &self.jpg_args {
+            jxl_args
+                .format_args
+                .insert(SourceFormat::Jpeg, args.clone());
+        }
+        for (format, args) in &self.format_args {
+            jxl_args.format_args.insert(*format, args.clone());
+        }
+        if let Some(depth) = self.zip_depth {
+            jxl_args.zip_depth = Some(depth);
+        }
+        if let Some(policy) = self.size_policy {
+            jxl_args.size_policy = policy;
+        }
+        if let Some(percent) = self.min_savings {
+            jxl_args.min_savings = percent;
+        }
+        if let Some(value) = self.comic {
+            jxl_args.comic = value;
+        }
+        jxl_args
+            .comic_folders
+            .extend(self.comic_folder.iter().cloned());
+        if let Some(path) = &self.cjxl {
+            jxl_args.cjxl_path = Some(path.clone());
+        }
+        if let Some(encoder) = self.encoder {
+            jxl_args.encoder = encoder;
+        }
+        if let Some(max_encodes) = self.max_encodes {
+            jxl_args.max_encodes = Some(max_encodes);
+        }
+        if let Some(memory_budget) = self.memory_budget {
+            jxl_args.memory_budget = Some(memory_budget);
+        }
+        if let Some(value) = self.low_priority {
+            jxl_args.low_priority = value;
+        }
+        let filter = &mut jxl_args.filter;
+        filter
+            .include_folders
+            .extend(self.include_folder.iter().cloned());
+        filter
+            .exclude_folders
+            .extend(self.exclude_folder.iter().cloned());
+        filter
+            .include_files
+            .extend(self.include_file.iter().cloned());
+        filter
+            .exclude_files
+            .extend(self.exclude_file.iter().cloned());
+        if let Some(bytes) = self.min_bytes {
+            jxl_args.min_bytes = bytes;
+        }
+        if let Some(pixels) = self.max_pixels {
+            jxl_args.max_pixels = pixels;
+        }
+        if let Some(days) = self.min_age_days {
+            jxl_args.min_age_days = days;
+        }
+    }
+}
+
+fn parse_format_args(value: &str) -> Result<(SourceFormat, Vec<String>), String> {
+    let (key, args) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected FORMAT:ARGS, got {}", value))?;
+    let format =
+        SourceFormat::from_config_key(key).ok_or_else(|| format!("unknown format: {}", key))?;
+    let args = args
+        .split(',')
+        .map(|arg| arg.trim().to_string())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    Ok((format, args))
+}
+
+//config 의 min_savings 와 같은 범위.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value.parse().map_err(|err| format!("{}: {}", value, err))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!(
+            "min_savings must be between 0 and 100, got {}",
+            percent
+        ));
+    }
+    Ok(percent)
+}
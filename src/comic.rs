@@ -0,0 +1,233 @@
+use crate::jxl::{self, BitReader};
+use image::io::Reader as ImageReader;
+use std::cmp::Ordering;
+use std::io::Cursor;
+use std::path::Path;
+
+//cbz 에 넣는 만화 정보 파일 이름. 대부분의 만화 뷰어가 이 이름으로 찾음.
+pub const COMIC_INFO_NAME: &str = "ComicInfo.xml";
+
+//ComicInfo.xml 의 <Page> 하나.
+pub struct ComicPage {
+    pub size: u64,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+//폴더 이름이 comic_folders 패턴 중 하나에 맞는지. 대소문자 구분 없음.
+pub fn is_comic_folder(folder_path: &Path, patterns: &[String]) -> bool {
+    let folder_name = folder_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    patterns
+        .iter()
+        .any(|pattern| wildcard_match(&pattern.to_lowercase(), &folder_name))
+}
+
+//* 는 아무 글자나 여러 개, ? 는 아무 글자 하나.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    //마지막으로 만난 * 의 위치와, 그 * 가 먹기 시작한 text 위치.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            //* 가 한 글자 더 먹도록 되돌아감.
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+//사람이 읽는 순서. "2.jpg" 가 "10.jpg" 보다 앞에 옴.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let a_len = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+                let b_len = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+                let a_num = a[..a_len].trim_start_matches('0');
+                let b_num = b[..b_len].trim_start_matches('0');
+                //앞의 0 을 뺀 자릿수가 많으면 큰 수. 자릿수가 같으면 글자 순서가 곧 크기 순서.
+                let ordering = a_num
+                    .len()
+                    .cmp(&b_num.len())
+                    .then_with(|| a_num.cmp(b_num))
+                    .then_with(|| a_len.cmp(&b_len));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(ca), Some(cb)) => {
+                let ordering = ca
+                    .to_lowercase()
+                    .cmp(cb.to_lowercase())
+                    .then_with(|| ca.cmp(&cb));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[ca.len_utf8()..];
+                b = &b[cb.len_utf8()..];
+            }
+        }
+    }
+}
+
+//페이지 크기. jxl 은 image 크레이트가 못 읽으므로 헤더를 직접 읽음.
+pub fn page_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    jxl_dimensions(data).or_else(|| {
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()
+    })
+}
+
+pub fn comic_info_xml(title: &str, pages: &[ComicPage]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+    );
+    xml.push_str(&format!("  <Title>{}</Title>\n", escape_xml(title)));
+    xml.push_str(&format!("  <PageCount>{}</PageCount>\n", pages.len()));
+    xml.push_str("  <Pages>\n");
+    for (index, page) in pages.iter().enumerate() {
+        xml.push_str(&format!(
+            "    <Page Image=\"{}\" ImageSize=\"{}\"",
+            index, page.size
+        ));
+        if let Some((width, height)) = page.dimensions {
+            xml.push_str(&format!(
+                " ImageWidth=\"{}\" ImageHeight=\"{}\"",
+                width, height
+            ));
+        }
+        if index == 0 {
+            xml.push_str(" Type=\"FrontCover\"");
+        }
+        xml.push_str(" />\n");
+    }
+    xml.push_str("  </Pages>\n</ComicInfo>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+//jxl 코드스트림 앞부분의 SizeHeader 를 읽음. 컨테이너(jpeg 재구성 데이터가 있는 경우)도 처리.
+fn jxl_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    codestream_dimensions(jxl::codestream(data)?)
+}
+
+fn codestream_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut bits = BitReader::new(data);
+    let small = bits.read(1)? == 1;
+    let height = if small {
+        (bits.read(5)? + 1) * 8
+    } else {
+        bits.read_size()?
+    };
+    let ratio = bits.read(3)?;
+    let width = match ratio {
+        0 if small => (bits.read(5)? + 1) * 8,
+        0 => bits.read_size()?,
+        _ => {
+            let (numerator, denominator) =
+                [(1, 1), (12, 10), (4, 3), (3, 2), (16, 9), (5, 4), (2, 1)][ratio as usize - 1];
+            (height as u64 * numerator / denominator) as u32
+        }
+    };
+
+    //ImageMetadata 의 orientation 이 5~8 이면 가로 세로가 바뀌어 보임.
+    let all_default = bits.read(1)? == 1;
+    if !all_default {
+        let extra_fields = bits.read(1)? == 1;
+        if extra_fields && bits.read(3)? + 1 > 4 {
+            return Some((height, width));
+        }
+    }
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        let mut names = vec!["10.jpg", "2.jpg", "1.jpg", "b.jpg", "A.jpg", "02.jpg"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["1.jpg", "2.jpg", "02.jpg", "10.jpg", "A.jpg", "b.jpg"]
+        );
+
+        assert_eq!(natural_cmp("page9", "page10"), Ordering::Less);
+        assert_eq!(natural_cmp("vol2/p10", "vol10/p1"), Ordering::Less);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+        //대소문자만 다르면 대문자가 앞.
+        assert_eq!(natural_cmp("A", "a"), Ordering::Less);
+        //u64 를 넘는 수도 자릿수로 비교함.
+        assert_eq!(
+            natural_cmp("99999999999999999999", "100000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn wildcard_match_handles_star_and_question_mark() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("comic*", "comics"));
+        assert!(wildcard_match("*manga*", "my manga folder"));
+        assert!(wildcard_match("vol?", "vol1"));
+        assert!(!wildcard_match("vol?", "vol10"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+        assert!(!wildcard_match("", "a"));
+        assert!(wildcard_match("", ""));
+        assert!(wildcard_match("만화*", "만화책"));
+    }
+
+    #[test]
+    fn is_comic_folder_ignores_case() {
+        let patterns = vec!["Comic*".to_string()];
+        assert!(is_comic_folder(Path::new("w/COMICS"), &patterns));
+        assert!(!is_comic_folder(Path::new("w/photos"), &patterns));
+    }
+
+    #[test]
+    fn jxl_dimensions_reads_small_size_header() {
+        //small, height (0 + 1) * 8, ratio 1:1, ImageMetadata all_default.
+        let codestream = [0xFF, 0x0A, 0x41, 0x02];
+        assert_eq!(jxl_dimensions(&codestream), Some((8, 8)));
+        //잘린 헤더는 읽지 않음.
+        assert_eq!(jxl_dimensions(&codestream[..3]), None);
+        assert_eq!(jxl_dimensions(&codestream[..2]), None);
+        assert_eq!(jxl_dimensions(&[]), None);
+    }
+}
@@ -0,0 +1,664 @@
+use crate::cli::JxlArgsOverride;
+use crate::encoder::EncoderKind;
+use crate::filter::Pattern;
+use crate::format::SourceFormat;
+use crate::metadata::MetadataKind;
+use crate::{JxlArgs, SizePolicy};
+use log::{info, warn};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+//기본 설정. 설정 파일이 하나도 없으면 사용자 설정 폴더에 이대로 씀.
+const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+//작업 폴더에 두는 설정 파일.
+pub const FOLDER_CONFIG_NAME: &str = ".jxlzip.toml";
+//예전 설정 파일. 더는 읽지 않음.
+const LEGACY_CONFIG_NAME: &str = "cjxl_args.ini";
+
+//설정 파일 하나. 모든 키는 없어도 되고, 있는 키만 앞의 설정을 덮어씀.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    delete_folder: Option<bool>,
+    delete_source_image: Option<bool>,
+    make_zip: Option<bool>,
+    dont_use_trashcan_just_delete: Option<bool>,
+    verify_image: Option<bool>,
+    encoder: Option<EncoderKind>,
+    cjxl_path: Option<PathBuf>,
+    zip_depth: Option<usize>,
+    size_policy: Option<SizePolicy>,
+    #[serde(default, deserialize_with = "percent")]
+    min_savings: Option<f64>,
+    comic: Option<bool>,
+    comic_folders: Option<Vec<String>>,
+    max_encodes: Option<NonZeroUsize>,
+    //MB
+    memory_budget: Option<NonZeroU64>,
+    low_priority: Option<bool>,
+    include_folders: Option<Vec<Pattern>>,
+    exclude_folders: Option<Vec<Pattern>>,
+    include_files: Option<Vec<Pattern>>,
+    exclude_files: Option<Vec<Pattern>>,
+    min_bytes: Option<u64>,
+    max_pixels: Option<u64>,
+    min_age_days: Option<u64>,
+    //[args] 표. 포맷별 cjxl 인자.
+    #[serde(default)]
+    args: BTreeMap<FormatKey, Vec<String>>,
+    //[strip] 표. 포맷별로 jxl 에서 뺄 메타데이터.
+    #[serde(default)]
+    strip: BTreeMap<FormatKey, Vec<MetadataKind>>,
+    //[profiles.이름] 표. 최상위에만 쓸 수 있음.
+    #[serde(default)]
+    profiles: BTreeMap<String, Spanned<Settings>>,
+}
+
+//[args], [strip] 의 키. jpg, png, ... 만 됨.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct FormatKey(SourceFormat);
+
+impl<'de> Deserialize<'de> for FormatKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        SourceFormat::from_config_key(&key)
+            .map(FormatKey)
+            .ok_or_else(|| {
+                let keys: Vec<&str> = SourceFormat::ALL
+                    .into_iter()
+                    .map(SourceFormat::config_key)
+                    .collect();
+                de::Error::custom(format!(
+                    "unknown format `{}`, expected one of {}",
+                    key,
+                    keys.join(", ")
+                ))
+            })
+    }
+}
+
+fn percent<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=100.0).contains(&value) {
+        return Err(de::Error::custom(format!(
+            "min_savings must be between 0 and 100, got {}",
+            value
+        )));
+    }
+    Ok(Some(value))
+}
+
+impl Settings {
+    fn apply(&self, jxl_args: &mut JxlArgs) {
+        if let Some(value) = self.delete_folder {
+            jxl_args.delete_folder_plag = value;
+        }
+        if let Some(value) = self.delete_source_image {
+            jxl_args.delete_source_image_plag = value;
+        }
+        if let Some(value) = self.make_zip {
+            jxl_args.make_zip_plag = value;
+        }
+        if let Some(value) = self.dont_use_trashcan_just_delete {
+            jxl_args.dont_use_trashcan_just_delete = value;
+        }
+        if let Some(value) = self.verify_image {
+            jxl_args.verify_image = value;
+        }
+        if let Some(encoder) = self.encoder {
+            jxl_args.encoder = encoder;
+        }
+        if let Some(path) = &self.cjxl_path {
+            jxl_args.cjxl_path = Some(path.clone());
+        }
+        if let Some(depth) = self.zip_depth {
+            jxl_args.zip_depth = Some(depth);
+        }
+        if let Some(policy) = self.size_policy {
+            jxl_args.size_policy = policy;
+        }
+        if let Some(percent) = self.min_savings {
+            jxl_args.min_savings = percent;
+        }
+        if let Some(value) = self.comic {
+            jxl_args.comic = value;
+        }
+        if let Some(patterns) = &self.comic_folders {
+            jxl_args.comic_folders = patterns.clone();
+        }
+        if let Some(max_encodes) = self.max_encodes {
+            jxl_args.max_encodes = Some(max_encodes.get());
+        }
+        if let Some(memory_budget) = self.memory_budget {
+            jxl_args.memory_budget = Some(memory_budget.get());
+        }
+        if let Some(value) = self.low_priority {
+            jxl_args.low_priority = value;
+        }
+        if let Some(patterns) = &self.include_folders {
+            jxl_args.filter.include_folders = patterns.clone();
+        }
+        if let Some(patterns) = &self.exclude_folders {
+            jxl_args.filter.exclude_folders = patterns.clone();
+        }
+        if let Some(patterns) = &self.include_files {
+            jxl_args.filter.include_files = patterns.clone();
+        }
+        if let Some(patterns) = &self.exclude_files {
+            jxl_args.filter.exclude_files = patterns.clone();
+        }
+        if let Some(bytes) = self.min_bytes {
+            jxl_args.min_bytes = bytes;
+        }
+        if let Some(pixels) = self.max_pixels {
+            jxl_args.max_pixels = pixels;
+        }
+        if let Some(days) = self.min_age_days {
+            jxl_args.min_age_days = days;
+        }
+        for (format, args) in &self.args {
+            jxl_args.format_args.insert(format.0, args.clone());
+        }
+        for (format, kinds) in &self.strip {
+            jxl_args.format_strip.insert(format.0, kinds.clone());
+        }
+    }
+}
+
+//읽은 설정 파일들. 뒤의 것이 앞의 것을 덮어씀. 작업 폴더의 .jxlzip.toml 은 FolderConfig 가 읽음.
+pub struct Config {
+    layers: Vec<(String, Settings)>,
+    //--config 로 준 파일. .jxlzip.toml 다음에 적용하므로 FolderConfig 가 가져감.
+    explicit: Option<(String, Settings)>,
+}
+
+impl Config {
+    //기본 설정, 사용자 설정, --config 파일을 읽음. 사용자 설정이 없으면 기본 설정을 씀. dry run 은 쓰지 않음.
+    pub fn load(explicit_path: Option<&Path>, dry_run: bool) -> Result<Config, String> {
+        if Path::new(LEGACY_CONFIG_NAME).is_file() {
+            warn!(
+                "{} is not read anymore. move the settings to {} or {}, see README",
+                LEGACY_CONFIG_NAME,
+                user_config_path()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|| "--config".to_string()),
+                FOLDER_CONFIG_NAME
+            );
+        }
+
+        let default = parse(DEFAULT_CONFIG).map_err(|err| format!("default config: {}", err))?;
+        let mut layers = vec![("default".to_string(), default)];
+        if let Some(user_path) = user_config_path() {
+            if user_path.is_file() {
+                layers.push(load_file(&user_path)?);
+            } else if !dry_run {
+                write_default_config(&user_path);
+            }
+        }
+        let explicit = explicit_path.map(load_file).transpose()?;
+        Ok(Config { layers, explicit })
+    }
+
+    //--config 파일을 뺀 설정 파일을 차례로 적용한 뒤 profile 을 적용함. profile 도 뒤의 파일에 있는 것이 덮어씀.
+    //profile 은 --config 파일에만 있어도 됨.
+    pub fn jxl_args(&self, profile: Option<&str>) -> Result<JxlArgs, String> {
+        let mut jxl_args = JxlArgs::default();
+        for (_, settings) in &self.layers {
+            settings.apply(&mut jxl_args);
+        }
+
+        let Some(name) = profile else {
+            return Ok(jxl_args);
+        };
+        let mut found = false;
+        for (_, settings) in &self.layers {
+            if let Some(profile) = settings.profiles.get(name) {
+                profile.get_ref().apply(&mut jxl_args);
+                found = true;
+            }
+        }
+        if let Some((_, explicit)) = &self.explicit {
+            found |= explicit.profiles.contains_key(name);
+        }
+        if !found {
+            let mut names: Vec<&str> = self
+                .layers
+                .iter()
+                .chain(&self.explicit)
+                .flat_map(|(_, settings)| settings.profiles.keys().map(String::as_str))
+                .collect();
+            names.sort_unstable();
+            names.dedup();
+            return Err(format!(
+                "unknown profile `{}`, expected one of {}",
+                name,
+                names.join(", ")
+            ));
+        }
+        Ok(jxl_args)
+    }
+
+    //읽은 파일들. 로그에 남김.
+    pub fn sources(&self) -> Vec<&str> {
+        self.layers
+            .iter()
+            .chain(&self.explicit)
+            .map(|(source, _)| source.as_str())
+            .collect()
+    }
+}
+
+//작업 폴더와 그 아래 폴더의 .jxlzip.toml. 위 폴더의 설정에 차례로 더하고, --config 파일과 명령줄 옵션은 폴더마다 마지막에 한 번 적용함.
+#[derive(Clone, Default)]
+pub struct FolderConfig {
+    //기본 설정과 사용자 설정, 그 profile 까지 적용한 값.
+    base: JxlArgs,
+    explicit: Option<Settings>,
+    profile: Option<String>,
+    overrides: JxlArgsOverride,
+}
+
+impl FolderConfig {
+    pub fn new(
+        config: &Config,
+        base: JxlArgs,
+        profile: Option<String>,
+        overrides: JxlArgsOverride,
+    ) -> Self {
+        FolderConfig {
+            base,
+            explicit: config
+                .explicit
+                .as_ref()
+                .map(|(_, settings)| settings.clone()),
+            profile,
+            overrides,
+        }
+    }
+
+    //파일의 키 다음에 --profile 과 같은 이름의 profile 을 적용함.
+    fn apply_file(&self, settings: &Settings, jxl_args: &mut JxlArgs) {
+        settings.apply(jxl_args);
+        if let Some(profile) = self
+            .profile
+            .as_ref()
+            .and_then(|name| settings.profiles.get(name))
+        {
+            profile.get_ref().apply(jxl_args);
+        }
+    }
+
+    //.jxlzip.toml 위에 --config 파일, 그 다음 명령줄 옵션을 적용함.
+    fn apply_explicit(&self, jxl_args: &mut JxlArgs) {
+        if let Some(explicit) = &self.explicit {
+            self.apply_file(explicit, jxl_args);
+        }
+        self.overrides.apply(jxl_args);
+    }
+
+    //.jxlzip.toml 이 없는 작업 폴더의 설정.
+    pub fn global_args(&self) -> JxlArgs {
+        let mut jxl_args = self.base.clone();
+        self.apply_explicit(&mut jxl_args);
+        jxl_args
+    }
+
+    //작업 폴더의 설정. .jxlzip.toml 이 없으면 global_args 와 같음.
+    pub fn work_folder_args(&self, work_folder: &Path) -> Result<FolderArgs, String> {
+        let path = work_folder.join(FOLDER_CONFIG_NAME);
+        if !path.is_file() {
+            return Ok(FolderArgs {
+                merged: Arc::new(self.base.clone()),
+                args: Arc::new(self.global_args()),
+            });
+        }
+        let (source, settings) = load_file(&path)?;
+        let mut merged = self.base.clone();
+        self.apply_file(&settings, &mut merged);
+        self.resolve(merged, &source)
+    }
+
+    //folder_path 에 .jxlzip.toml 이 없으면 None. 위 폴더의 설정을 그대로 물려받음.
+    //있으면 위 폴더의 merged 에 더하므로 폴더를 읽을 때 파일을 한 번만 읽음.
+    //작업 폴더 전체에 하나뿐인 설정은 작업 폴더의 파일에서만 바꿀 수 있음.
+    pub fn subfolder_args(
+        &self,
+        parent: &FolderArgs,
+        work: &FolderArgs,
+        folder_path: &Path,
+    ) -> Result<Option<FolderArgs>, String> {
+        let path = folder_path.join(FOLDER_CONFIG_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let (source, settings) = load_file(&path)?;
+        let mut merged = (*parent.merged).clone();
+        self.apply_file(&settings, &mut merged);
+        let ignored = keep_work_folder_fields(&mut merged, &work.merged);
+        if !ignored.is_empty() {
+            warn!(
+                "{}: {} can only be set for the whole work folder, ignored",
+                source,
+                ignored.join(", ")
+            );
+        }
+        self.resolve(merged, &source).map(Some)
+    }
+
+    //.jxlzip.toml 까지 적용한 값 위에 --config 파일과 명령줄 옵션을 한 번 적용하고 확인함.
+    fn resolve(&self, merged: JxlArgs, source: &str) -> Result<FolderArgs, String> {
+        let mut jxl_args = merged.clone();
+        self.apply_explicit(&mut jxl_args);
+        if jxl_args.args_for(SourceFormat::Png).is_none()
+            || jxl_args.args_for(SourceFormat::Jpeg).is_none()
+        {
+            return Err(format!(
+                "{}: args.png and args.jpg must not be empty",
+                source
+            ));
+        }
+        jxl_args
+            .check_strip()
+            .map_err(|err| format!("{}: {}", source, err))?;
+        Ok(FolderArgs {
+            merged: Arc::new(merged),
+            args: Arc::new(jxl_args),
+        })
+    }
+}
+
+//폴더 하나의 설정. 하위 폴더는 merged 위에 자기 .jxlzip.toml 을 더함.
+#[derive(Clone, Debug)]
+pub struct FolderArgs {
+    //.jxlzip.toml 까지 적용한 값.
+    pub merged: Arc<JxlArgs>,
+    //그 위에 --config 파일과 명령줄 옵션을 적용한 값. 변환에 씀.
+    pub args: Arc<JxlArgs>,
+}
+
+//인코더, 동시 실행 수, zip 묶는 깊이는 작업 폴더마다 하나. 하위 폴더의 값은 되돌리고 그 키를 돌려줌.
+fn keep_work_folder_fields(jxl_args: &mut JxlArgs, work_args: &JxlArgs) -> Vec<&'static str> {
+    let mut ignored = vec![];
+    if jxl_args.encoder != work_args.encoder {
+        jxl_args.encoder = work_args.encoder;
+        ignored.push("encoder");
+    }
+    if jxl_args.cjxl_path != work_args.cjxl_path {
+        jxl_args.cjxl_path = work_args.cjxl_path.clone();
+        ignored.push("cjxl_path");
+    }
+    if jxl_args.zip_depth != work_args.zip_depth {
+        jxl_args.zip_depth = work_args.zip_depth;
+        ignored.push("zip_depth");
+    }
+    if jxl_args.max_encodes != work_args.max_encodes {
+        jxl_args.max_encodes = work_args.max_encodes;
+        ignored.push("max_encodes");
+    }
+    if jxl_args.memory_budget != work_args.memory_budget {
+        jxl_args.memory_budget = work_args.memory_budget;
+        ignored.push("memory_budget");
+    }
+    if jxl_args.low_priority != work_args.low_priority {
+        jxl_args.low_priority = work_args.low_priority;
+        ignored.push("low_priority");
+    }
+    ignored
+}
+
+//폴더의 .jxlzip.toml. 변환하거나 zip 에 넣지 않음.
+pub fn is_folder_config(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == FOLDER_CONFIG_NAME)
+}
+
+//리눅스 ~/.config, 윈도우 %APPDATA%, 맥 ~/Library/Application Support 아래.
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("jxl_zip_maker").join("config.toml"))
+}
+
+fn load_file(path: &Path) -> Result<(String, Settings), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read config {}: {}", path.display(), err))?;
+    let settings = parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok((path.display().to_string(), settings))
+}
+
+//모르는 키나 틀린 값은 toml 에러에 줄 번호가 들어감.
+fn parse(text: &str) -> Result<Settings, String> {
+    let settings: Settings = toml::from_str(text).map_err(|err| err.to_string())?;
+    for (name, profile) in &settings.profiles {
+        if !profile.get_ref().profiles.is_empty() {
+            let line = text[..profile.span().start].lines().count() + 1;
+            return Err(format!(
+                "line {}: profile `{}` can not have profiles",
+                line, name
+            ));
+        }
+    }
+    Ok(settings)
+}
+
+fn write_default_config(path: &Path) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(path, DEFAULT_CONFIG));
+    match result {
+        Ok(()) => info!("Default config written: {}", path.display()),
+        Err(err) => warn!(
+            "Failed to write default config: {}\nerror message: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(source: &str, text: &str) -> (String, Settings) {
+        (source.to_string(), parse(text).unwrap())
+    }
+
+    fn parse_err(text: &str) -> String {
+        match parse(text) {
+            Ok(_) => panic!("parsed: {}", text),
+            Err(err) => err,
+        }
+    }
+
+    fn png_args(jxl_args: &JxlArgs) -> Vec<String> {
+        jxl_args
+            .args_for(SourceFormat::Png)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn effort(effort: u8) -> Vec<String> {
+        vec!["--distance=0".to_string(), format!("--effort={}", effort)]
+    }
+
+    //테스트마다 따로 쓰는 작업 폴더와 그 아래 sub 폴더.
+    fn work_folder(name: &str, work_config: &str, sub_config: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("jxl_zip_maker_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join(FOLDER_CONFIG_NAME), work_config).unwrap();
+        fs::write(dir.join("sub").join(FOLDER_CONFIG_NAME), sub_config).unwrap();
+        dir
+    }
+
+    #[test]
+    fn later_layers_override_earlier_keys() {
+        let config = Config {
+            layers: vec![
+                layer("default", DEFAULT_CONFIG),
+                layer(
+                    "user",
+                    "make_zip = false\n[args]\npng = [\"--distance=0\", \"--effort=3\"]\n",
+                ),
+            ],
+            explicit: None,
+        };
+        let jxl_args = config.jxl_args(None).unwrap();
+        assert!(!jxl_args.make_zip_plag);
+        assert_eq!(png_args(&jxl_args), effort(3));
+        //user 에 없는 키는 default 의 값.
+        assert_eq!(
+            jxl_args
+                .args_for(SourceFormat::Jpeg)
+                .unwrap()
+                .last()
+                .unwrap(),
+            "--lossless_jpeg=1"
+        );
+    }
+
+    #[test]
+    fn profile_is_applied_after_every_layer() {
+        let config = Config {
+            layers: vec![
+                layer(
+                    "default",
+                    "[args]\npng = [\"--distance=0\", \"--effort=7\"]\n[profiles.fast]\nargs.png = [\"--distance=0\", \"--effort=1\"]\n",
+                ),
+                layer("user", "[args]\npng = [\"--distance=0\", \"--effort=3\"]\n"),
+            ],
+            explicit: Some(layer("explicit", "[profiles.only_explicit]\nmake_zip = false\n")),
+        };
+        assert_eq!(png_args(&config.jxl_args(None).unwrap()), effort(3));
+        assert_eq!(png_args(&config.jxl_args(Some("fast")).unwrap()), effort(1));
+        //--config 에만 있는 profile 은 FolderConfig 가 적용하므로 여기서는 그대로.
+        assert_eq!(
+            png_args(&config.jxl_args(Some("only_explicit")).unwrap()),
+            effort(3)
+        );
+        let err = config.jxl_args(Some("missing")).unwrap_err();
+        assert!(err.contains("unknown profile `missing`"), "{}", err);
+        assert!(err.contains("fast, only_explicit"), "{}", err);
+    }
+
+    #[test]
+    fn explicit_config_and_overrides_come_after_folder_configs() {
+        let dir = work_folder(
+            "config_order",
+            "make_zip = false\n[args]\npng = [\"--distance=0\", \"--effort=3\"]\n[profiles.fast]\nargs.png = [\"--distance=0\", \"--effort=2\"]\n",
+            "[args]\npng = [\"--distance=0\", \"--effort=4\"]\n",
+        );
+        let config = Config {
+            layers: vec![layer("default", DEFAULT_CONFIG)],
+            explicit: Some(layer(
+                "explicit",
+                "[args]\npng = [\"--distance=0\", \"--effort=5\"]\n",
+            )),
+        };
+        let no_explicit = Config {
+            layers: vec![layer("default", DEFAULT_CONFIG)],
+            explicit: None,
+        };
+        let base = config.jxl_args(None).unwrap();
+
+        let folder_config =
+            FolderConfig::new(&no_explicit, base.clone(), None, JxlArgsOverride::default());
+        let work = folder_config.work_folder_args(&dir).unwrap();
+        assert_eq!(png_args(&work.args), effort(3));
+        assert!(!work.args.make_zip_plag);
+        let sub = folder_config
+            .subfolder_args(&work, &work, &dir.join("sub"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(png_args(&sub.args), effort(4));
+        assert!(!sub.args.make_zip_plag);
+
+        //.jxlzip.toml 의 profile 은 그 파일의 키 다음.
+        let folder_config = FolderConfig::new(
+            &no_explicit,
+            base.clone(),
+            Some("fast".to_string()),
+            JxlArgsOverride::default(),
+        );
+        let work = folder_config.work_folder_args(&dir).unwrap();
+        assert_eq!(png_args(&work.args), effort(2));
+
+        let folder_config =
+            FolderConfig::new(&config, base.clone(), None, JxlArgsOverride::default());
+        let work = folder_config.work_folder_args(&dir).unwrap();
+        let sub = folder_config
+            .subfolder_args(&work, &work, &dir.join("sub"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(png_args(&work.args), effort(5));
+        assert_eq!(png_args(&sub.args), effort(5));
+        //merged 에는 --config 가 들어가지 않으므로 하위 폴더가 다시 덮어쓸 수 있음.
+        assert_eq!(png_args(&sub.merged), effort(4));
+        assert!(!sub.args.make_zip_plag);
+
+        let overrides = JxlArgsOverride {
+            png_args: Some(effort(6)),
+            ..Default::default()
+        };
+        let folder_config = FolderConfig::new(&config, base, None, overrides);
+        let work = folder_config.work_folder_args(&dir).unwrap();
+        let sub = folder_config
+            .subfolder_args(&work, &work, &dir.join("sub"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(png_args(&work.args), effort(6));
+        assert_eq!(png_args(&sub.args), effort(6));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn subfolder_can_not_change_work_folder_fields() {
+        let dir = work_folder(
+            "work_fields",
+            "zip_depth = 1\n",
+            "zip_depth = 2\nmake_zip = false\n",
+        );
+        let config = Config {
+            layers: vec![layer("default", DEFAULT_CONFIG)],
+            explicit: None,
+        };
+        let folder_config = FolderConfig::new(
+            &config,
+            config.jxl_args(None).unwrap(),
+            None,
+            JxlArgsOverride::default(),
+        );
+        let work = folder_config.work_folder_args(&dir).unwrap();
+        let sub = folder_config
+            .subfolder_args(&work, &work, &dir.join("sub"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(sub.args.zip_depth, Some(1));
+        assert!(!sub.args.make_zip_plag);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_key_and_bad_value_report_the_line() {
+        let err = parse_err("make_zip = true\n\nmake_zipp = true\n");
+        assert!(err.contains("line 3"), "{}", err);
+        assert!(err.contains("unknown field `make_zipp`"), "{}", err);
+
+        let err = parse_err("[args]\npng = [\"--distance=0\"]\ngiff = [\"--effort=1\"]\n");
+        assert!(err.contains("line 3"), "{}", err);
+        assert!(err.contains("unknown format `giff`"), "{}", err);
+
+        let err = parse_err("min_savings = 101\n");
+        assert!(err.contains("line 1"), "{}", err);
+
+        let err =
+            parse_err("[profiles.a]\nmake_zip = true\n[profiles.a.profiles.b]\nmake_zip = false\n");
+        assert!(err.contains("profile `a` can not have profiles"), "{}", err);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::encoder::{self, EncoderKind};
+use crate::format::SourceFormat;
+use crate::interrupt::{self, PartialFile};
+use crate::jxl;
+use crate::JxlArgs;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use log::info;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//djxl 위치를 찾을 때 쓰는 환경 변수.
+pub const DJXL_ENV: &str = "DJXL_PATH";
+
+//djxl 임시 파일 이름을 스레드마다 다르게 만듦.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub trait Decoder: Send + Sync {
+    //jxl 에 들어있는 jpeg 재구성 데이터로 원본 jpeg 를 만듦.
+    fn reconstruct_jpeg(&self, jxl_path: &Path) -> Result<Vec<u8>, String>;
+
+    //jxl 을 픽셀로 디코딩.
+    fn decode_pixels(&self, jxl_path: &Path) -> Result<DynamicImage, String>;
+}
+
+pub fn build_decoder(cjxl_args: &JxlArgs) -> Result<Box<dyn Decoder>, String> {
+    match cjxl_args.encoder {
+        EncoderKind::Cjxl => {
+            //djxl 은 보통 cjxl 과 같은 폴더에 있음.
+            let cjxl_dir: Vec<PathBuf> = encoder::find_cjxl(cjxl_args.cjxl_path.as_deref())
+                .ok()
+                .and_then(|cjxl| cjxl.parent().map(Path::to_path_buf))
+                .into_iter()
+                .collect();
+            let exe_path = encoder::find_program("djxl", DJXL_ENV, None, &cjxl_dir)?;
+            info!("current djxl location: {:?}", exe_path);
+            Ok(Box::new(DjxlDecoder { exe_path }))
+        }
+        #[cfg(feature = "libjxl")]
+        EncoderKind::Libjxl => Ok(Box::new(libjxl::LibjxlDecoder)),
+        #[cfg(not(feature = "libjxl"))]
+        EncoderKind::Libjxl => Err(
+            "this build has no in-process decoder. rebuild with `--features libjxl`".to_string(),
+        ),
+    }
+}
+
+pub struct DjxlDecoder {
+    exe_path: PathBuf,
+}
+
+impl DjxlDecoder {
+    //djxl 은 출력 확장자로 포맷을 정함. 임시 폴더의 파일에 풀고 읽은 뒤 지움.
+    //원본 옆에 같은 이름의 파일이 있을 수 있으므로 jxl 옆에는 만들지 않음.
+    fn decode_to_temp<T>(
+        &self,
+        jxl_path: &Path,
+        extension: &str,
+        read: impl FnOnce(&Path) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let temp_path = env::temp_dir().join(format!(
+            "jxl_zip_maker_check_{}_{}.{}",
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        //이미 있는 파일은 덮어쓰거나 지우지 않음.
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .map_err(|err| format!("Failed to create {}: {}", temp_path.display(), err))?;
+        let output = interrupt::run_child(
+            Command::new(&self.exe_path).arg(jxl_path).arg(&temp_path),
+            &temp_path,
+        )
+        .map_err(|err| format!("Failed to run djxl: {}", err))?;
+        let result = if output.status.success() {
+            read(&temp_path)
+        } else {
+            Err(format!(
+                "djxl failed\nstderr: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        };
+        let _ = fs::remove_file(&temp_path);
+        result
+    }
+}
+
+impl Decoder for DjxlDecoder {
+    fn reconstruct_jpeg(&self, jxl_path: &Path) -> Result<Vec<u8>, String> {
+        self.decode_to_temp(jxl_path, "jpg", |path| {
+            fs::read(path).map_err(|err| err.to_string())
+        })
+    }
+
+    fn decode_pixels(&self, jxl_path: &Path) -> Result<DynamicImage, String> {
+        self.decode_to_temp(jxl_path, "png", |path| {
+            image::open(path).map_err(|err| err.to_string())
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verified {
+    //jpeg 재구성 결과가 원본과 바이트 단위로 같음.
+    JpegExact,
+    //무손실 설정이고 픽셀이 모두 같음.
+    PixelExact,
+    //손실 설정이라 디코딩되는지만 확인.
+    Decodable,
+}
+
+//cjxl 인자에서 값 찾기. "--distance=0", "-d=0", "-d", "0" 모두 읽음.
+fn arg_value<'a>(args: &'a [String], long: &str, short: &str) -> Option<&'a str> {
+    let mut value = None;
+    let mut iter = args.iter().map(|arg| arg.trim());
+    while let Some(arg) = iter.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, inline_value)) => (name, Some(inline_value)),
+            None => (arg, None),
+        };
+        if name == long || name == short {
+            value = inline_value.or_else(|| iter.next());
+        }
+    }
+    value
+}
+
+fn is_lossless(args: &[String]) -> bool {
+    let distance_zero = arg_value(args, "--distance", "-d")
+        .and_then(|value| value.parse::<f32>().ok())
+        .is_some_and(|distance| distance == 0.0);
+    let quality_max = arg_value(args, "--quality", "-q")
+        .and_then(|value| value.parse::<f32>().ok())
+        .is_some_and(|quality| quality >= 100.0);
+    distance_zero || quality_max
+}
+
+//cjxl 은 jpeg 입력을 기본으로 재구성 가능하게 변환함. --lossless_jpeg=0 일 때만 아님.
+pub fn keeps_jpeg(args: &[String]) -> bool {
+    arg_value(args, "--lossless_jpeg", "-j") != Some("0")
+}
+
+fn same_pixels(source: &DynamicImage, decoded: &DynamicImage) -> bool {
+    if source.dimensions() != decoded.dimensions() {
+        return false;
+    }
+    let is_float = |image: &DynamicImage| {
+        matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        )
+    };
+    //16bit 까지는 rgba16 으로 맞춰서 비교. 8bit 는 정확히 257배가 되므로 손실 없음.
+    if is_float(source) || is_float(decoded) {
+        source.to_rgba32f().as_raw() == decoded.to_rgba32f().as_raw()
+    } else {
+        source.to_rgba16().as_raw() == decoded.to_rgba16().as_raw()
+    }
+}
+
+//변환된 jxl 이 원본과 같은지 확인. 다르면 Err.
+pub fn verify_jxl(
+    decoder: &dyn Decoder,
+    cjxl_args: &JxlArgs,
+    image_path: &Path,
+    jxl_path: &Path,
+    image_format: ImageFormat,
+) -> Result<Verified, String> {
+    let source_format = SourceFormat::from_image_format(image_format)
+        .ok_or_else(|| "Not supported file type".to_string())?;
+    let args = cjxl_args
+        .args_for(source_format)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    if source_format == SourceFormat::Jpeg && keeps_jpeg(args) {
+        let reconstructed = decoder.reconstruct_jpeg(jxl_path)?;
+        let original = fs::read(image_path).map_err(|err| err.to_string())?;
+        return if reconstructed == original {
+            Ok(Verified::JpegExact)
+        } else {
+            Err("Reconstructed jpeg is different from original".to_string())
+        };
+    }
+
+    let decoded = decoder.decode_pixels(jxl_path)?;
+    if source_format == SourceFormat::Jpeg || !is_lossless(args) {
+        return Ok(Verified::Decodable);
+    }
+
+    let source = image::open(image_path).map_err(|err| err.to_string())?;
+    if same_pixels(&source, &decoded) {
+        Ok(Verified::PixelExact)
+    } else {
+        Err("Decoded pixels are different from original".to_string())
+    }
+}
+
+//jxl 컨테이너에 jpeg 재구성 데이터(jbrd 상자)가 있는지. 맨 코드스트림이면 없음.
+pub fn has_jpeg_reconstruction(data: &[u8]) -> bool {
+    jxl::boxes(data)
+        .into_iter()
+        .any(|(box_type, _)| box_type == b"jbrd")
+}
+
+//jxl 을 원래 포맷으로 되돌리고 jxl 을 지움. jpeg 재구성 데이터가 있으면 원본 jpeg 그대로, 없으면 png.
+pub fn restore_jxl(decoder: &dyn Decoder, jxl_path: &Path) -> Result<PathBuf, String> {
+    let data = fs::read(jxl_path).map_err(|err| err.to_string())?;
+    let is_jpeg = has_jpeg_reconstruction(&data);
+    let output_path = jxl_path.with_extension(if is_jpeg { "jpg" } else { "png" });
+    if output_path.exists() {
+        return Err(format!("{} already exists", output_path.display()));
+    }
+
+    let partial = PartialFile::new(&output_path);
+    let result = if is_jpeg {
+        decoder
+            .reconstruct_jpeg(jxl_path)
+            .and_then(|jpeg| fs::write(&output_path, jpeg).map_err(|err| err.to_string()))
+    } else {
+        decoder.decode_pixels(jxl_path).and_then(|image| {
+            image
+                .save_with_format(&output_path, ImageFormat::Png)
+                .map_err(|err| err.to_string())
+        })
+    };
+    if let Err(err) = result {
+        let _ = fs::remove_file(&output_path);
+        return Err(err);
+    }
+    drop(partial);
+    fs::remove_file(jxl_path).map_err(|err| err.to_string())?;
+    Ok(output_path)
+}
+
+#[cfg(feature = "libjxl")]
+mod libjxl {
+    use super::Decoder;
+    use image::{DynamicImage, ImageBuffer};
+    use jpegxl_rs::decode::{decoder_builder, Data};
+    use std::fs;
+    use std::path::Path;
+
+    pub struct LibjxlDecoder;
+
+    impl Decoder for LibjxlDecoder {
+        fn reconstruct_jpeg(&self, jxl_path: &Path) -> Result<Vec<u8>, String> {
+            let data = fs::read(jxl_path).map_err(|err| err.to_string())?;
+            let decoder = decoder_builder().build().map_err(|err| err.to_string())?;
+            match decoder.reconstruct(&data).map_err(|err| err.to_string())? {
+                (_, Data::Jpeg(jpeg)) => Ok(jpeg),
+                (_, Data::Pixels(_)) => Err("No jpeg reconstruction data in jxl".to_string()),
+            }
+        }
+
+        fn decode_pixels(&self, jxl_path: &Path) -> Result<DynamicImage, String> {
+            let data = fs::read(jxl_path).map_err(|err| err.to_string())?;
+            let decoder = decoder_builder().build().map_err(|err| err.to_string())?;
+            let (metadata, pixels) = decoder
+                .decode_with::<u16>(&data)
+                .map_err(|err| err.to_string())?;
+            let (width, height) = (metadata.width, metadata.height);
+            let image = match (metadata.num_color_channels, metadata.has_alpha_channel) {
+                (1, false) => {
+                    ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma16)
+                }
+                (1, true) => {
+                    ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA16)
+                }
+                (3, false) => {
+                    ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb16)
+                }
+                (3, true) => {
+                    ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba16)
+                }
+                _ => None,
+            };
+            image.ok_or_else(|| "Unexpected channel layout in jxl".to_string())
+        }
+    }
+}
@@ -0,0 +1,580 @@
+use crate::format::{self, SourceFormat};
+use crate::interrupt::{self, PartialFile};
+use crate::limits::LimitedEncoder;
+use crate::metadata::{self, MetadataKind};
+use crate::JxlArgs;
+use clap::ValueEnum;
+use image::ImageFormat;
+use log::{debug, info};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+//cjxl 위치를 찾을 때 쓰는 환경 변수.
+pub const CJXL_ENV: &str = "CJXL_PATH";
+
+//cjxl 찾는 순서: --cjxl 옵션(또는 설정 파일의 cjxl_path) -> 환경 변수 -> 실행 파일 폴더 -> 현재 폴더 -> PATH
+//--cjxl 옵션은 설정 파일을 덮어쓰므로, 둘 다 explicit_path 로 들어옴.
+pub fn find_cjxl(explicit_path: Option<&Path>) -> Result<PathBuf, String> {
+    find_program("cjxl", CJXL_ENV, explicit_path, &[]).map_err(|err| {
+        format!(
+            "{}\ngive --cjxl or cjxl_path in the config, or see README",
+            err
+        )
+    })
+}
+
+//name 프로그램 찾는 순서: explicit_path -> 환경 변수 -> first_dirs -> 실행 파일 폴더 -> 현재 폴더 -> PATH
+pub fn find_program(
+    name: &str,
+    env_key: &str,
+    explicit_path: Option<&Path>,
+    first_dirs: &[PathBuf],
+) -> Result<PathBuf, String> {
+    if let Some(path) = explicit_path {
+        return if path.is_file() {
+            Ok(path.to_path_buf())
+        } else {
+            Err(format!("{} not exists: {}", name, path.display()))
+        };
+    }
+
+    if let Some(path) = env::var_os(env_key) {
+        let path = PathBuf::from(path);
+        return if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!(
+                "{} in {} not exists: {}",
+                name,
+                env_key,
+                path.display()
+            ))
+        };
+    }
+
+    let file_name = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    let mut candidates: Vec<PathBuf> = first_dirs.iter().map(|dir| dir.join(&file_name)).collect();
+    if let Some(exe_dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(exe_dir.join(&file_name));
+    }
+    //예전처럼 현재 폴더에 cjxl.exe 를 두는 경우.
+    if let Ok(current_dir) = env::current_dir() {
+        candidates.push(current_dir.join(&file_name));
+    }
+    if let Some(paths) = env::var_os("PATH") {
+        candidates.extend(env::split_paths(&paths).map(|dir| dir.join(&file_name)));
+    }
+
+    for candidate in candidates {
+        debug!("looking for {}: {}", name, candidate.display());
+        if candidate.is_file() {
+            return Ok(candidate);
+        }
+    }
+    Err(format!(
+        "{} not found. set {}, or put it next to this program or in PATH",
+        file_name, env_key
+    ))
+}
+
+//cjxl --version 의 첫 줄. e.g. "JPEG XL encoder v0.10.2 4451ce9 [AVX2,SSE4,SSE2]"
+pub fn cjxl_version(exe_path: &Path) -> Result<String, String> {
+    let output = Command::new(exe_path)
+        .arg("--version")
+        .output()
+        .map_err(|err| {
+            format!(
+                "Failed to run {}\nerror message: {}",
+                exe_path.display(),
+                err
+            )
+        })?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to run {} --version\nerror message: {}",
+            exe_path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    //버전에 따라 stdout 이나 stderr 로 출력됨.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let version = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("unknown")
+        .to_string();
+    info!("cjxl version: {}", version);
+    Ok(version)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncoderKind {
+    //cjxl 프로세스를 이미지마다 실행.
+    #[default]
+    Cjxl,
+    //libjxl 을 프로세스 안에서 직접 호출. `libjxl` feature 가 필요함.
+    Libjxl,
+}
+
+pub trait Encoder: Send + Sync {
+    //image_path 를 jxl_path 에 jxl 로 저장. cjxl_args 는 이미지가 있는 폴더의 설정.
+    fn encode(
+        &self,
+        image_path: &Path,
+        jxl_path: &Path,
+        image_format: ImageFormat,
+        cjxl_args: &JxlArgs,
+    ) -> Result<(), String>;
+
+    //작업 내역에 남길 인코더 버전.
+    fn version(&self) -> &str;
+}
+
+pub fn build_encoder(cjxl_args: &JxlArgs) -> Result<Box<dyn Encoder>, String> {
+    let encoder: Box<dyn Encoder> = match cjxl_args.encoder {
+        EncoderKind::Cjxl => {
+            let exe_path = find_cjxl(cjxl_args.cjxl_path.as_deref())?;
+            info!("current cjxl location: {:?}", exe_path);
+            Box::new(CjxlEncoder::new(exe_path)?)
+        }
+        #[cfg(feature = "libjxl")]
+        EncoderKind::Libjxl => Box::new(libjxl::LibjxlEncoder::new(cjxl_args)?),
+        #[cfg(not(feature = "libjxl"))]
+        EncoderKind::Libjxl => {
+            return Err(
+                "this build has no in-process encoder. rebuild with `--features libjxl`"
+                    .to_string(),
+            )
+        }
+    };
+    if cjxl_args.max_encodes.is_none() && cjxl_args.memory_budget.is_none() {
+        return Ok(encoder);
+    }
+    Ok(Box::new(LimitedEncoder::new(
+        encoder,
+        cjxl_args.max_encodes,
+        cjxl_args.memory_budget.map(|mb| mb << 20),
+    )))
+}
+
+pub struct CjxlEncoder {
+    exe_path: PathBuf,
+    version: String,
+}
+
+impl CjxlEncoder {
+    pub fn new(exe_path: PathBuf) -> Result<Self, String> {
+        let version = cjxl_version(&exe_path)?;
+        Ok(CjxlEncoder { exe_path, version })
+    }
+
+    fn run_cjxl(
+        &self,
+        input_path: &Path,
+        jxl_path: &Path,
+        args: &[String],
+        image_path: &Path,
+    ) -> Result<(), String> {
+        let output = interrupt::run_child(
+            Command::new(&self.exe_path)
+                .arg(input_path)
+                .arg(jxl_path)
+                .args(args),
+            jxl_path,
+        )
+        .map_err(|err| {
+            format!(
+                "Failed file: {} \nerror message: Failed to run cjxl: {}",
+                image_path.display(),
+                err
+            )
+        })?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "Failed file: {} \nerror message: {}",
+                image_path.display(),
+                {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    format!("stdout: {}\nstderr: {}", stdout, stderr)
+                }
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Encoder for CjxlEncoder {
+    fn encode(
+        &self,
+        image_path: &Path,
+        jxl_path: &Path,
+        image_format: ImageFormat,
+        cjxl_args: &JxlArgs,
+    ) -> Result<(), String> {
+        let (source_format, args) = SourceFormat::from_image_format(image_format)
+            .and_then(|format| Some((format, cjxl_args.args_for(format)?)))
+            .ok_or_else(|| {
+                format!(
+                    "Failed file: {} \nerror message: Not supported file type",
+                    image_path.display(),
+                )
+            })?;
+
+        //cjxl 은 exif, xmp 를 빼는 옵션만 있음.
+        let strip = cjxl_args.strip_for(source_format);
+        let mut args = args.clone();
+        for kind in strip {
+            match kind {
+                MetadataKind::Exif => args.extend(["-x".to_string(), "strip=exif".to_string()]),
+                MetadataKind::Xmp => args.extend(["-x".to_string(), "strip=xmp".to_string()]),
+                MetadataKind::Icc => {}
+            }
+        }
+        let args = &args;
+
+        if source_format.cjxl_reads_directly() && !strip.contains(&MetadataKind::Icc) {
+            return self.run_cjxl(image_path, jxl_path, args, image_path);
+        }
+
+        //cjxl 이 못 읽는 포맷과 icc 를 뺄 이미지는 무손실 png 를 거쳐서 변환. png 에는 메타데이터가 남지 않음.
+        check_not_animated(image_path, source_format)?;
+        check_icc_kept(image_path, source_format, strip)?;
+        let png_path = jxl_path.with_extension("jxl_tmp.png");
+        let _partial = PartialFile::new(&png_path);
+        format::decode_to_png(image_path, &png_path).map_err(|err| {
+            let _ = fs::remove_file(&png_path);
+            format!(
+                "Failed file: {} \nerror message: {}",
+                image_path.display(),
+                err
+            )
+        })?;
+        let result = self.run_cjxl(&png_path, jxl_path, args, image_path);
+        let _ = fs::remove_file(&png_path);
+        result
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+}
+
+//움직이는 이미지는 첫 프레임만 남으므로 변환하지 않음.
+fn check_not_animated(image_path: &Path, source_format: SourceFormat) -> Result<(), String> {
+    match format::is_animated(image_path, source_format) {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(format!(
+            "Failed file: {} \nerror message: Animated {} can not be converted without cjxl reading it directly",
+            image_path.display(),
+            source_format.config_key()
+        )),
+        Err(err) => Err(format!(
+            "Failed file: {} \nerror message: {}",
+            image_path.display(),
+            err
+        )),
+    }
+}
+
+//디코딩한 픽셀에는 icc 가 따라가지 않음. sRGB 가 아닌 icc 는 색이 바뀌므로 strip 에 없으면 변환하지 않음.
+fn check_icc_kept(
+    image_path: &Path,
+    source_format: SourceFormat,
+    strip: &[MetadataKind],
+) -> Result<(), String> {
+    if strip.contains(&MetadataKind::Icc) {
+        return Ok(());
+    }
+    let data = fs::read(image_path).map_err(|err| {
+        format!(
+            "Failed file: {} \nerror message: {}",
+            image_path.display(),
+            err
+        )
+    })?;
+    if metadata::has_non_srgb_icc(&data, source_format) {
+        return Err(format!(
+            "Failed file: {} \nerror message: the icc profile would be lost when decoding the {}, add icc to strip.{} to convert it without the profile",
+            image_path.display(),
+            source_format.config_key(),
+            source_format.config_key()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "libjxl")]
+mod libjxl {
+    use super::{check_icc_kept, check_not_animated, Encoder};
+    use crate::format::SourceFormat;
+    use crate::JxlArgs;
+    use image::{DynamicImage, ImageFormat};
+    use jpegxl_rs::encode::{encoder_builder, EncoderResult, EncoderSpeed};
+    use std::fs;
+    use std::path::Path;
+
+    //png_args, jpg_args 중 in-process 인코더가 이해하는 값들.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct EncodeSettings {
+        //0 이면 무손실.
+        pub distance: f32,
+        //1 ~ 10
+        pub effort: u8,
+        //jpeg 를 재구성 가능한 무손실 jxl 로 변환.
+        pub lossless_jpeg: bool,
+    }
+
+    impl Default for EncodeSettings {
+        //cjxl 기본값과 같음.
+        fn default() -> Self {
+            EncodeSettings {
+                distance: 1.0,
+                effort: 7,
+                lossless_jpeg: true,
+            }
+        }
+    }
+
+    impl EncodeSettings {
+        //"--distance=0", "-d=0", "-d", "0" 같은 cjxl 인자를 읽음. 모르는 인자는 에러.
+        pub fn from_args(args: &[String]) -> Result<Self, String> {
+            let mut settings = EncodeSettings::default();
+            let mut iter = args
+                .iter()
+                .map(|arg| arg.trim())
+                .filter(|arg| !arg.is_empty());
+            while let Some(arg) = iter.next() {
+                let (name, value) = match arg.split_once('=') {
+                    Some((name, value)) => (name, value.to_string()),
+                    None => match iter.next() {
+                        Some(value) => (arg, value.to_string()),
+                        None => return Err(format!("Missing value of cjxl argument: {}", arg)),
+                    },
+                };
+                match name {
+                    "--distance" | "-d" => {
+                        settings.distance = value
+                            .parse()
+                            .map_err(|_| format!("Invalid distance: {}", value))?;
+                    }
+                    "--effort" | "-e" => {
+                        settings.effort = match value.parse() {
+                            Ok(effort @ 1..=10) => effort,
+                            _ => return Err(format!("Invalid effort: {}", value)),
+                        };
+                    }
+                    "--lossless_jpeg" | "-j" => {
+                        settings.lossless_jpeg = match value.as_str() {
+                            "1" => true,
+                            "0" => false,
+                            _ => return Err(format!("Invalid lossless_jpeg: {}", value)),
+                        };
+                    }
+                    _ => {
+                        return Err(format!(
+                            "cjxl argument not supported by the in-process encoder: {}",
+                            arg
+                        ))
+                    }
+                }
+            }
+            Ok(settings)
+        }
+    }
+
+    pub struct LibjxlEncoder {
+        //링크된 libjxl 의 버전. 버전이 바뀌면 작업 내역의 설정 id 도 바뀜.
+        version: String,
+    }
+
+    impl LibjxlEncoder {
+        //작업 폴더의 인자를 미리 읽어봄. 하위 폴더의 인자는 변환할 때 읽음.
+        pub fn new(cjxl_args: &JxlArgs) -> Result<Self, String> {
+            for format in SourceFormat::ALL {
+                format_settings(cjxl_args, format)?;
+            }
+            //major * 1000000 + minor * 1000 + patch
+            let version = unsafe { jpegxl_sys::encode::JxlEncoderVersion() };
+            Ok(LibjxlEncoder {
+                version: format!(
+                    "libjxl {}.{}.{} in-process",
+                    version / 1_000_000,
+                    version / 1_000 % 1_000,
+                    version % 1_000
+                ),
+            })
+        }
+    }
+
+    //인자를 읽고, 이 인코더로 지킬 수 없는 strip 은 에러.
+    //재구성 데이터를 남기는 jpeg 는 메타데이터를 모두 남기고, 나머지는 픽셀만 넘기므로 메타데이터가 모두 빠짐.
+    fn format_settings(
+        cjxl_args: &JxlArgs,
+        format: SourceFormat,
+    ) -> Result<Option<EncodeSettings>, String> {
+        let Some(args) = cjxl_args.args_for(format) else {
+            return Ok(None);
+        };
+        let settings = EncodeSettings::from_args(args)
+            .map_err(|err| format!("{}_args: {}", format.config_key(), err))?;
+        if format == SourceFormat::Jpeg
+            && settings.lossless_jpeg
+            && !cjxl_args.strip_for(format).is_empty()
+        {
+            return Err(format!(
+                "strip.{} can not be used by the in-process libjxl encoder with --lossless_jpeg=1, it keeps all metadata",
+                format.config_key()
+            ));
+        }
+        Ok(Some(settings))
+    }
+
+    fn speed(effort: u8) -> EncoderSpeed {
+        match effort {
+            1 => EncoderSpeed::Lightning,
+            2 => EncoderSpeed::Thunder,
+            3 => EncoderSpeed::Falcon,
+            4 => EncoderSpeed::Cheetah,
+            5 => EncoderSpeed::Hare,
+            6 => EncoderSpeed::Wombat,
+            7 => EncoderSpeed::Squirrel,
+            8 => EncoderSpeed::Kitten,
+            9 => EncoderSpeed::Tortoise,
+            _ => EncoderSpeed::Glacier,
+        }
+    }
+
+    fn encode_file(
+        image_path: &Path,
+        image_format: ImageFormat,
+        settings: &EncodeSettings,
+    ) -> Result<Vec<u8>, String> {
+        let lossless = settings.distance == 0.0;
+
+        //jpeg 재구성 데이터를 남기는 변환은 픽셀을 디코딩하지 않음.
+        if image_format == ImageFormat::Jpeg && settings.lossless_jpeg {
+            let data = fs::read(image_path).map_err(|err| err.to_string())?;
+            //재구성 데이터(jbrd)는 컨테이너에 들어감.
+            let mut encoder = encoder_builder()
+                .use_container(true)
+                .speed(speed(settings.effort))
+                .build()
+                .map_err(|err| err.to_string())?;
+            let result: EncoderResult<u8> =
+                encoder.encode_jpeg(&data).map_err(|err| err.to_string())?;
+            return Ok(result.data);
+        }
+
+        let image = image::open(image_path).map_err(|err| err.to_string())?;
+        //f32 이미지(exr)는 8bit 로 줄여서 넘기므로 무손실이 되지 않음.
+        if lossless
+            && matches!(
+                image,
+                DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+            )
+        {
+            return Err(
+                "32-bit float image can not be encoded losslessly by the in-process libjxl encoder, use cjxl"
+                    .to_string(),
+            );
+        }
+        let has_alpha = image.color().has_alpha();
+        let mut encoder = encoder_builder()
+            .has_alpha(has_alpha)
+            .lossless(lossless)
+            .uses_original_profile(lossless)
+            .quality(settings.distance)
+            .speed(speed(settings.effort))
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        //16bit 이미지는 16bit 그대로 넘겨야 무손실이 됨.
+        let high_bit_depth = matches!(
+            image,
+            DynamicImage::ImageLuma16(_)
+                | DynamicImage::ImageLumaA16(_)
+                | DynamicImage::ImageRgb16(_)
+                | DynamicImage::ImageRgba16(_)
+        );
+        let (width, height) = (image.width(), image.height());
+        let data = match (high_bit_depth, has_alpha) {
+            (true, true) => encoder
+                .encode::<u16, u16>(image.to_rgba16().as_raw(), width, height)
+                .map(|result| result.data),
+            (true, false) => encoder
+                .encode::<u16, u16>(image.to_rgb16().as_raw(), width, height)
+                .map(|result| result.data),
+            (false, true) => encoder
+                .encode::<u8, u8>(image.to_rgba8().as_raw(), width, height)
+                .map(|result| result.data),
+            (false, false) => encoder
+                .encode::<u8, u8>(image.to_rgb8().as_raw(), width, height)
+                .map(|result| result.data),
+        }
+        .map_err(|err| err.to_string())?;
+        Ok(data)
+    }
+
+    impl Encoder for LibjxlEncoder {
+        fn encode(
+            &self,
+            image_path: &Path,
+            jxl_path: &Path,
+            image_format: ImageFormat,
+            cjxl_args: &JxlArgs,
+        ) -> Result<(), String> {
+            let failed = |err: String| {
+                format!(
+                    "Failed file: {} \nerror message: {}",
+                    image_path.display(),
+                    err
+                )
+            };
+            let source_format = SourceFormat::from_image_format(image_format)
+                .ok_or_else(|| failed("Not supported file type".to_string()))?;
+            let settings = format_settings(cjxl_args, source_format)
+                .map_err(failed)?
+                .ok_or_else(|| failed("Not supported file type".to_string()))?;
+            check_not_animated(image_path, source_format)?;
+            //jpeg 재구성 데이터를 남기지 않으면 픽셀만 넘어감.
+            if !(source_format == SourceFormat::Jpeg && settings.lossless_jpeg) {
+                check_icc_kept(
+                    image_path,
+                    source_format,
+                    cjxl_args.strip_for(source_format),
+                )?;
+            }
+            let data = encode_file(image_path, image_format, &settings).map_err(|err| {
+                format!(
+                    "Failed file: {} \nerror message: {}",
+                    image_path.display(),
+                    err
+                )
+            })?;
+            fs::write(jxl_path, data).map_err(|err| {
+                format!(
+                    "Failed file: {} \nerror message: Failed to write jxl: {}",
+                    image_path.display(),
+                    err
+                )
+            })
+        }
+
+        fn version(&self) -> &str {
+            &self.version
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+//이 파일이 있는 폴더는 하위 폴더까지 읽지 않음.
+pub const NO_JXL_MARKER: &str = ".nojxl";
+
+//include/exclude 패턴 하나. 대소문자 구분 없음.
+//re: 로 시작하면 작업 폴더 기준 상대 경로에 대한 정규식.
+//glob 에 / 가 있으면 상대 경로에, 없으면 이름에 맞춤. 경로 구분자는 항상 / 로 씀.
+#[derive(Clone)]
+pub struct Pattern {
+    source: String,
+    matcher: Matcher,
+}
+
+#[derive(Clone)]
+enum Matcher {
+    Name(GlobMatcher),
+    Path(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Pattern, String> {
+        let matcher = if let Some(regex) = source.strip_prefix("re:") {
+            let regex = RegexBuilder::new(regex)
+                .case_insensitive(true)
+                .build()
+                .map_err(|err| format!("invalid regex `{}`: {}", source, err))?;
+            Matcher::Regex(regex)
+        } else {
+            let glob = GlobBuilder::new(source.trim_end_matches('/'))
+                .case_insensitive(true)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| format!("invalid glob `{}`: {}", source, err))?
+                .compile_matcher();
+            if source.contains('/') {
+                Matcher::Path(glob)
+            } else {
+                Matcher::Name(glob)
+            }
+        };
+        Ok(Pattern {
+            source: source.to_string(),
+            matcher,
+        })
+    }
+
+    //relative 는 / 로 이은 상대 경로. 폴더면 / 로 끝나서 **/DCIM/** 가 DCIM 폴더에도 맞음.
+    fn is_match(&self, relative: &str) -> bool {
+        let path = relative.trim_end_matches('/');
+        match &self.matcher {
+            Matcher::Name(glob) => glob.is_match(path.rsplit('/').next().unwrap_or(path)),
+            Matcher::Path(glob) => glob.is_match(path) || glob.is_match(relative),
+            Matcher::Regex(regex) => regex.is_match(relative),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source).map_err(de::Error::custom)
+    }
+}
+
+//폴더와 파일을 고르는 패턴들. 작업 내역에는 남기지 않음.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct PathFilter {
+    //비어 있지 않으면, 폴더나 그 위 폴더가 하나에 맞아야 그 폴더의 이미지를 변환함.
+    pub include_folders: Vec<Pattern>,
+    //맞는 폴더는 하위 폴더까지 읽지 않음.
+    pub exclude_folders: Vec<Pattern>,
+    //비어 있지 않으면, 하나에 맞는 이미지만 변환함.
+    pub include_files: Vec<Pattern>,
+    //맞는 이미지는 변환하지 않음.
+    pub exclude_files: Vec<Pattern>,
+}
+
+impl PathFilter {
+    //하위 폴더까지 건너뛸 폴더면 그 이유.
+    pub fn skip_folder(&self, work_folder: &Path, folder_path: &Path) -> Option<String> {
+        if folder_path.join(NO_JXL_MARKER).is_file() {
+            return Some(format!("{} marker", NO_JXL_MARKER));
+        }
+        let relative = relative_path(work_folder, folder_path) + "/";
+        first_match(&self.exclude_folders, &relative)
+            .map(|pattern| format!("exclude_folders {:?}", pattern))
+    }
+
+    //include_folders 에 폴더 자신이 맞는지. 위 폴더가 맞았는지는 부르는 쪽이 앎.
+    pub fn includes_folder(&self, work_folder: &Path, folder_path: &Path) -> bool {
+        let relative = relative_path(work_folder, folder_path) + "/";
+        self.include_folders.is_empty() || first_match(&self.include_folders, &relative).is_some()
+    }
+
+    //변환하지 않을 이미지면 그 이유.
+    pub fn skip_file(&self, work_folder: &Path, file_path: &Path) -> Option<String> {
+        let relative = relative_path(work_folder, file_path);
+        if let Some(pattern) = first_match(&self.exclude_files, &relative) {
+            return Some(format!("exclude_files {:?}", pattern));
+        }
+        if !self.include_files.is_empty() && first_match(&self.include_files, &relative).is_none() {
+            return Some("not in include_files".to_string());
+        }
+        None
+    }
+
+    //zip, cbz 면 exclude_files 만 봄. include_files 는 이미지를 고르는 패턴이라 보지 않음.
+    pub fn skip_archive(&self, work_folder: &Path, archive_path: &Path) -> Option<String> {
+        let relative = relative_path(work_folder, archive_path);
+        first_match(&self.exclude_files, &relative)
+            .map(|pattern| format!("exclude_files {:?}", pattern))
+    }
+}
+
+fn first_match<'a>(patterns: &'a [Pattern], relative: &str) -> Option<&'a Pattern> {
+    patterns.iter().find(|pattern| pattern.is_match(relative))
+}
+
+//작업 폴더 기준 상대 경로를 / 로 이음. 작업 폴더 자신은 빈 문자열.
+fn relative_path(work_folder: &Path, path: &Path) -> String {
+    path.strip_prefix(work_folder)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(sources: &[&str]) -> Vec<Pattern> {
+        sources
+            .iter()
+            .map(|source| Pattern::new(source).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn glob_without_slash_matches_name() {
+        let pattern = Pattern::new("*.PNG").unwrap();
+        assert!(pattern.is_match("a.png"));
+        assert!(pattern.is_match("deep/folder/a.Png"));
+        assert!(!pattern.is_match("a.png.bak"));
+
+        let pattern = Pattern::new("node_modules").unwrap();
+        assert!(pattern.is_match("web/node_modules/"));
+        assert!(!pattern.is_match("web/node_modules_old/"));
+    }
+
+    #[test]
+    fn glob_with_slash_matches_relative_path() {
+        let pattern = Pattern::new("photos/*.jpg").unwrap();
+        assert!(pattern.is_match("photos/a.jpg"));
+        //* 는 / 를 넘지 않음.
+        assert!(!pattern.is_match("photos/2020/a.jpg"));
+        assert!(!pattern.is_match("other/photos/a.jpg"));
+
+        let pattern = Pattern::new("**/DCIM/**").unwrap();
+        assert!(pattern.is_match("phone/dcim/"));
+        assert!(pattern.is_match("phone/DCIM/100/a.jpg"));
+        assert!(!pattern.is_match("phone/DCIM2/a.jpg"));
+
+        //끝의 / 는 무시함.
+        let pattern = Pattern::new("raw/").unwrap();
+        assert!(pattern.is_match("raw/"));
+    }
+
+    #[test]
+    fn regex_matches_relative_path_ignoring_case() {
+        let pattern = Pattern::new(r"re:^scans/\d+/$").unwrap();
+        assert!(pattern.is_match("SCANS/2020/"));
+        assert!(!pattern.is_match("scans/2020/a.png"));
+        assert!(!pattern.is_match("old/scans/2020/"));
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        assert!(Pattern::new("re:(")
+            .unwrap_err()
+            .contains("invalid regex `re:(`"));
+        assert!(Pattern::new("a[")
+            .unwrap_err()
+            .contains("invalid glob `a[`"));
+    }
+
+    #[test]
+    fn path_filter_checks_files_and_folders() {
+        let work_folder = Path::new("w");
+        let filter = PathFilter {
+            include_folders: patterns(&["comics"]),
+            exclude_folders: patterns(&["**/tmp/**"]),
+            include_files: patterns(&["*.png", "*.zip"]),
+            exclude_files: patterns(&["skip*"]),
+        };
+
+        assert!(filter.includes_folder(work_folder, Path::new("w/comics")));
+        assert!(!filter.includes_folder(work_folder, Path::new("w/comics/vol1")));
+        assert!(PathFilter::default().includes_folder(work_folder, Path::new("w/any")));
+
+        assert_eq!(
+            filter.skip_folder(work_folder, Path::new("w/a/tmp")),
+            Some("exclude_folders \"**/tmp/**\"".to_string())
+        );
+        assert_eq!(filter.skip_folder(work_folder, Path::new("w/a/tmp2")), None);
+
+        assert_eq!(filter.skip_file(work_folder, Path::new("w/a/b.png")), None);
+        assert_eq!(
+            filter.skip_file(work_folder, Path::new("w/a/skip.png")),
+            Some("exclude_files \"skip*\"".to_string())
+        );
+        assert_eq!(
+            filter.skip_file(work_folder, Path::new("w/a/b.jpg")),
+            Some("not in include_files".to_string())
+        );
+
+        //zip 은 include_files 를 보지 않음.
+        let filter = PathFilter {
+            include_files: patterns(&["*.png"]),
+            exclude_files: patterns(&["skip*"]),
+            ..Default::default()
+        };
+        assert_eq!(filter.skip_archive(work_folder, Path::new("w/a.zip")), None);
+        assert_eq!(
+            filter.skip_archive(work_folder, Path::new("w/skip.cbz")),
+            Some("exclude_files \"skip*\"".to_string())
+        );
+    }
+
+    #[test]
+    fn relative_path_uses_slashes() {
+        assert_eq!(relative_path(Path::new("w"), Path::new("w")), "");
+        assert_eq!(
+            relative_path(Path::new("w"), &Path::new("w").join("a").join("b.png")),
+            "a/b.png"
+        );
+        //작업 폴더 밖이면 경로 그대로.
+        assert_eq!(relative_path(Path::new("w"), Path::new("x/a")), "x/a");
+    }
+}
@@ -0,0 +1,137 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::io::Reader as ImageReader;
+use image::{AnimationDecoder, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+//jxl 로 변환하는 원본 이미지 포맷.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum SourceFormat {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    Bmp,
+    Tiff,
+    Pnm,
+    Qoi,
+    OpenExr,
+}
+
+impl SourceFormat {
+    pub const ALL: [SourceFormat; 9] = [
+        SourceFormat::Jpeg,
+        SourceFormat::Png,
+        SourceFormat::Gif,
+        SourceFormat::WebP,
+        SourceFormat::Bmp,
+        SourceFormat::Tiff,
+        SourceFormat::Pnm,
+        SourceFormat::Qoi,
+        SourceFormat::OpenExr,
+    ];
+
+    pub fn from_image_format(image_format: ImageFormat) -> Option<Self> {
+        match image_format {
+            ImageFormat::Jpeg => Some(SourceFormat::Jpeg),
+            ImageFormat::Png => Some(SourceFormat::Png),
+            ImageFormat::Gif => Some(SourceFormat::Gif),
+            ImageFormat::WebP => Some(SourceFormat::WebP),
+            ImageFormat::Bmp => Some(SourceFormat::Bmp),
+            ImageFormat::Tiff => Some(SourceFormat::Tiff),
+            ImageFormat::Pnm => Some(SourceFormat::Pnm),
+            ImageFormat::Qoi => Some(SourceFormat::Qoi),
+            ImageFormat::OpenExr => Some(SourceFormat::OpenExr),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let ext = ext.to_lowercase();
+        SourceFormat::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&ext.as_str()))
+    }
+
+    //설정 파일의 [args], [strip] 과 --format-args {key}:... 에 쓰는 이름.
+    pub fn config_key(self) -> &'static str {
+        match self {
+            SourceFormat::Jpeg => "jpg",
+            SourceFormat::Png => "png",
+            SourceFormat::Gif => "gif",
+            SourceFormat::WebP => "webp",
+            SourceFormat::Bmp => "bmp",
+            SourceFormat::Tiff => "tiff",
+            SourceFormat::Pnm => "pnm",
+            SourceFormat::Qoi => "qoi",
+            SourceFormat::OpenExr => "exr",
+        }
+    }
+
+    pub fn from_config_key(key: &str) -> Option<Self> {
+        SourceFormat::ALL
+            .into_iter()
+            .find(|format| format.config_key() == key)
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            SourceFormat::Jpeg => &["jpg", "jpeg"],
+            SourceFormat::Png => &["png"],
+            SourceFormat::Gif => &["gif"],
+            SourceFormat::WebP => &["webp"],
+            SourceFormat::Bmp => &["bmp"],
+            SourceFormat::Tiff => &["tif", "tiff"],
+            SourceFormat::Pnm => &["pbm", "pgm", "ppm", "pam"],
+            SourceFormat::Qoi => &["qoi"],
+            SourceFormat::OpenExr => &["exr"],
+        }
+    }
+
+    //cjxl 이 직접 읽는 포맷. 나머지는 image 크레이트로 디코딩해서 png 로 넘김.
+    pub fn cjxl_reads_directly(self) -> bool {
+        matches!(
+            self,
+            SourceFormat::Jpeg
+                | SourceFormat::Png
+                | SourceFormat::Gif
+                | SourceFormat::Pnm
+                | SourceFormat::OpenExr
+        )
+    }
+}
+
+//움직이는 gif, webp 는 image 크레이트로 디코딩하면 첫 프레임만 남음.
+pub fn is_animated(image_path: &Path, format: SourceFormat) -> Result<bool, String> {
+    let open = || {
+        File::open(image_path)
+            .map(BufReader::new)
+            .map_err(|err| err.to_string())
+    };
+    match format {
+        SourceFormat::Gif => {
+            let decoder = GifDecoder::new(open()?).map_err(|err| err.to_string())?;
+            Ok(decoder.into_frames().take(2).count() > 1)
+        }
+        SourceFormat::WebP => {
+            let decoder = WebPDecoder::new(open()?).map_err(|err| err.to_string())?;
+            Ok(decoder.has_animation())
+        }
+        _ => Ok(false),
+    }
+}
+
+//cjxl 이 못 읽는 포맷을 무손실 png 로 풀어둠. 16bit 까지는 그대로 유지됨.
+pub fn decode_to_png(image_path: &Path, png_path: &Path) -> Result<(), String> {
+    let image = ImageReader::open(image_path)
+        .map_err(|err| err.to_string())?
+        .decode()
+        .map_err(|err| err.to_string())?;
+    //png 는 f32 를 저장할 수 없어서, 무손실로 바꿀 수 없는 이미지는 실패로 처리함.
+    image
+        .save_with_format(png_path, ImageFormat::Png)
+        .map_err(|err| format!("Failed to make lossless intermediate png: {}", err))
+}
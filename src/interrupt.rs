@@ -0,0 +1,115 @@
+use log::warn;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+//Ctrl-C 를 한 번 누르면 새 작업을 시작하지 않고, 돌고 있는 인코더가 끝나기를 기다린 뒤 작업 내역을 저장하고 끝냄.
+//한 번 더 누르면 인코더를 죽이고 쓰다 만 파일을 지운 뒤 바로 끝냄.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+//돌고 있는 cjxl, djxl.
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(vec![]);
+//쓰고 있는 파일. 바로 끝낼 때 지움.
+static PARTIAL_FILES: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+//Ctrl-C 로 끝날 때의 종료 코드.
+pub const EXIT_CODE: u8 = 130;
+
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            abort();
+        }
+        warn!("Ctrl-C: waiting for running encoders, then saving the worklist. press Ctrl-C again to abort now");
+    })
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+fn abort() -> ! {
+    //자식이 죽으면 기다리던 스레드가 목록에서 빼므로 먼저 가져옴.
+    let partial_files: Vec<PathBuf> = PARTIAL_FILES.lock().unwrap().drain(..).collect();
+    for pid in CHILDREN.lock().unwrap().drain(..) {
+        kill(pid);
+    }
+    for path in partial_files {
+        let _ = fs::remove_file(path);
+    }
+    warn!("Ctrl-C again: aborted");
+    process::exit(EXIT_CODE as i32);
+}
+
+//바로 끝낼 때 지울 파일. 값이 사라지면 목록에서 뺌.
+pub struct PartialFile(PathBuf);
+
+impl PartialFile {
+    pub fn new(path: &Path) -> Self {
+        PARTIAL_FILES.lock().unwrap().push(path.to_path_buf());
+        PartialFile(path.to_path_buf())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        let mut partial_files = PARTIAL_FILES.lock().unwrap();
+        if let Some(index) = partial_files.iter().position(|path| *path == self.0) {
+            partial_files.swap_remove(index);
+        }
+    }
+}
+
+//자식 프로세스를 따로 프로세스 그룹에 넣어서 첫 Ctrl-C 를 받지 않게 하고, 끝날 때까지 기다림.
+//output 은 자식이 쓰는 파일. 두 번째 Ctrl-C 에서 지움.
+pub fn run_child(command: &mut Command, output: &Path) -> io::Result<Output> {
+    detach(command);
+    let _partial = PartialFile::new(output);
+    let child = command.spawn()?;
+    let pid = child.id();
+    CHILDREN.lock().unwrap().push(pid);
+    let result = child.wait_with_output();
+    CHILDREN.lock().unwrap().retain(|&child| child != pid);
+    result
+}
+
+#[cfg(unix)]
+fn detach(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+fn detach(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP;
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+#[cfg(not(any(unix, windows)))]
+fn detach(_command: &mut Command) {}
+
+#[cfg(unix)]
+fn kill(pid: u32) {
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill(pid: u32) {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE};
+    unsafe {
+        let process = OpenProcess(PROCESS_TERMINATE, 0, pid);
+        if process != 0 {
+            TerminateProcess(process, 1);
+            CloseHandle(process);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn kill(_pid: u32) {}
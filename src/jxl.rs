@@ -0,0 +1,216 @@
+//jxl 파일을 디코딩하지 않고 읽는 부분. 컨테이너 상자와 코드스트림 헤더.
+
+const CONTAINER_SIGNATURE: [u8; 12] = [
+    0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+];
+const CODESTREAM_SIGNATURE: [u8; 2] = [0xFF, 0x0A];
+
+//jxl 컨테이너의 상자들. 맨 코드스트림이거나 컨테이너가 아니면 비어있음.
+pub fn boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = vec![];
+    let Some(mut rest) = data.strip_prefix(&CONTAINER_SIGNATURE) else {
+        return boxes;
+    };
+    while rest.len() >= 8 {
+        let (header_len, box_len) = match u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) {
+            //마지막 상자는 파일 끝까지.
+            0 => (8, rest.len() as u64),
+            1 => match rest.get(8..16) {
+                Some(size) => (16, u64::from_be_bytes(size.try_into().unwrap())),
+                None => break,
+            },
+            size => (8, size as u64),
+        };
+        if box_len < header_len as u64 || box_len > rest.len() as u64 {
+            break;
+        }
+        boxes.push((&rest[4..8], &rest[header_len..box_len as usize]));
+        rest = &rest[box_len as usize..];
+    }
+    boxes
+}
+
+//코드스트림의 시그니처 뒤부터. 맨 코드스트림과 컨테이너의 jxlc, jxlp 상자를 모두 처리.
+pub fn codestream(data: &[u8]) -> Option<&[u8]> {
+    if let Some(codestream) = data.strip_prefix(&CODESTREAM_SIGNATURE) {
+        return Some(codestream);
+    }
+    boxes(data)
+        .into_iter()
+        .find_map(|(box_type, payload)| match box_type {
+            b"jxlc" => Some(payload),
+            //나눠진 코드스트림은 4바이트 순번 뒤에 옴. 첫 조각에 헤더가 있음.
+            b"jxlp" => payload.get(4..),
+            _ => None,
+        })?
+        .strip_prefix(&CODESTREAM_SIGNATURE)
+}
+
+//jxl 은 바이트 안에서 낮은 비트부터 읽음.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    pub fn read(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position / 8)?;
+            let bit = (byte >> (self.position % 8)) & 1;
+            value |= (bit as u32) << i;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    //SizeHeader 의 U32(Bits(9)+1, Bits(13)+1, Bits(18)+1, Bits(30)+1).
+    pub fn read_size(&mut self) -> Option<u32> {
+        let bits = [9, 13, 18, 30][self.read(2)? as usize];
+        Some(self.read(bits)? + 1)
+    }
+
+    //U32 필드. 2비트로 고른 (offset, 비트 수). Val(v) 는 (v, 0).
+    pub fn read_u32(&mut self, distribution: [(u32, usize); 4]) -> Option<u32> {
+        let (offset, bits) = distribution[self.read(2)? as usize];
+        Some(offset.wrapping_add(self.read(bits)?))
+    }
+
+    //SizeHeader 를 건너뜀.
+    pub fn skip_size_header(&mut self) -> Option<()> {
+        let small = self.read(1)? == 1;
+        if small {
+            self.read(5)?;
+        } else {
+            self.read_size()?;
+        }
+        if self.read(3)? == 0 {
+            if small {
+                self.read(5)?;
+            } else {
+                self.read_size()?;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jxl_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn container(boxes: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = CONTAINER_SIGNATURE.to_vec();
+        for jxl_box in boxes {
+            data.extend_from_slice(jxl_box);
+        }
+        data
+    }
+
+    #[test]
+    fn boxes_reads_sized_to_end_and_large_boxes() {
+        let mut large = 1u32.to_be_bytes().to_vec();
+        large.extend_from_slice(b"Exif");
+        large.extend_from_slice(&20u64.to_be_bytes());
+        large.extend_from_slice(b"exif");
+        let mut to_end = 0u32.to_be_bytes().to_vec();
+        to_end.extend_from_slice(b"jxlc");
+        to_end.extend_from_slice(&[0xFF, 0x0A, 1, 2]);
+        let data = container(&[jxl_box(b"ftyp", b"jxl \0\0\0\0jxl "), large, to_end]);
+
+        let boxes = boxes(&data);
+        assert_eq!(boxes.len(), 3);
+        assert_eq!(boxes[0], (&b"ftyp"[..], &b"jxl \0\0\0\0jxl "[..]));
+        assert_eq!(boxes[1], (&b"Exif"[..], &b"exif"[..]));
+        assert_eq!(boxes[2], (&b"jxlc"[..], &[0xFF, 0x0A, 1, 2][..]));
+    }
+
+    #[test]
+    fn boxes_stops_at_truncated_box() {
+        let complete = jxl_box(b"ftyp", b"jxl \0\0\0\0jxl ");
+        let exif = jxl_box(b"Exif", b"exif data");
+        let data = container(&[complete.clone(), exif]);
+        //상자 내용이 잘리면 그 앞 상자까지만.
+        assert_eq!(boxes(&data[..data.len() - 1]).len(), 1);
+        //상자 머리가 잘려도 마찬가지.
+        assert_eq!(
+            boxes(&data[..CONTAINER_SIGNATURE.len() + complete.len() + 5]).len(),
+            1
+        );
+
+        //64비트 크기가 잘린 상자.
+        let mut large = 1u32.to_be_bytes().to_vec();
+        large.extend_from_slice(b"Exif");
+        large.extend_from_slice(&[0, 0, 0]);
+        assert!(boxes(&container(&[large])).is_empty());
+
+        //크기가 머리보다 작은 상자.
+        let mut small = 4u32.to_be_bytes().to_vec();
+        small.extend_from_slice(b"Exif");
+        assert!(boxes(&container(&[small])).is_empty());
+
+        assert!(boxes(&CONTAINER_SIGNATURE[..11]).is_empty());
+        assert!(boxes(&[0xFF, 0x0A, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn codestream_finds_bare_jxlc_and_jxlp() {
+        assert_eq!(codestream(&[0xFF, 0x0A, 1, 2]), Some(&[1, 2][..]));
+        assert_eq!(codestream(&[0xFF]), None);
+        assert_eq!(codestream(&[]), None);
+
+        let jxlc = container(&[
+            jxl_box(b"ftyp", b"jxl "),
+            jxl_box(b"jxlc", &[0xFF, 0x0A, 3]),
+        ]);
+        assert_eq!(codestream(&jxlc), Some(&[3][..]));
+
+        let jxlp = container(&[jxl_box(b"jxlp", &[0, 0, 0, 0, 0xFF, 0x0A, 4])]);
+        assert_eq!(codestream(&jxlp), Some(&[4][..]));
+
+        //순번이 잘린 jxlp, 시그니처가 없는 jxlc, 잘린 jxlc.
+        assert_eq!(codestream(&container(&[jxl_box(b"jxlp", &[0, 0])])), None);
+        assert_eq!(codestream(&container(&[jxl_box(b"jxlc", &[1, 2])])), None);
+        assert_eq!(codestream(&jxlc[..jxlc.len() - 1]), None);
+    }
+
+    #[test]
+    fn bit_reader_reads_low_bits_first_and_stops_at_end() {
+        let mut bits = BitReader::new(&[0b1010_0110, 0b0000_0001]);
+        assert_eq!(bits.read(1), Some(0));
+        assert_eq!(bits.read(3), Some(0b011));
+        assert_eq!(bits.read(5), Some(0b1_1010));
+        assert_eq!(bits.read(7), Some(0));
+        assert_eq!(bits.read(1), None);
+
+        //선택 2비트 01 이면 Bits(13)+1.
+        let mut bits = BitReader::new(&[0b0000_0101, 0]);
+        assert_eq!(bits.read_size(), Some(2));
+        let mut bits = BitReader::new(&[0b0000_0101]);
+        assert_eq!(bits.read_size(), None);
+
+        //U32(Val(8), Val(10), Val(12), BitsOffset(6, 1)) 의 Val(10).
+        let mut bits = BitReader::new(&[0b01]);
+        assert_eq!(bits.read_u32([(8, 0), (10, 0), (12, 0), (1, 6)]), Some(10));
+    }
+
+    #[test]
+    fn skip_size_header_fails_on_truncated_header() {
+        //small, ratio 0 이면 width 5비트가 더 있음.
+        let header = [0b0000_0001, 0b0000_0000];
+        assert_eq!(BitReader::new(&header).skip_size_header(), Some(()));
+        assert_eq!(BitReader::new(&header[..1]).skip_size_header(), None);
+        assert_eq!(BitReader::new(&[]).skip_size_header(), None);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::encoder::Encoder;
+use crate::JxlArgs;
+use image::io::Reader as ImageReader;
+use image::ImageFormat;
+use log::debug;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+
+//cjxl 이 픽셀 하나에 쓰는 메모리 어림값. float 4채널 버퍼 몇 개 분량. effort 가 높으면 더 씀.
+//24MP 스캔 한 장이 1GB 정도로 잡힘.
+const BYTES_PER_PIXEL: u64 = 48;
+
+//동시에 도는 인코더 수와, 이미지 크기로 어림한 메모리 합을 제한하는 인코더.
+//한도를 넘는 이미지는 앞의 변환이 끝날 때까지 기다림.
+pub struct LimitedEncoder {
+    inner: Box<dyn Encoder>,
+    max_encodes: Option<usize>,
+    memory_budget: Option<u64>,
+    running: Mutex<Running>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct Running {
+    encodes: usize,
+    memory: u64,
+}
+
+impl LimitedEncoder {
+    //memory_budget 은 바이트.
+    pub fn new(
+        inner: Box<dyn Encoder>,
+        max_encodes: Option<usize>,
+        memory_budget: Option<u64>,
+    ) -> Self {
+        LimitedEncoder {
+            inner,
+            max_encodes,
+            memory_budget,
+            running: Mutex::new(Running::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self, memory: u64) {
+        let mut running = self.running.lock().unwrap();
+        //혼자서도 한도를 넘는 이미지는 다른 인코더가 모두 끝나면 돌림. 안 그러면 영원히 기다림.
+        while running.encodes > 0
+            && (self
+                .max_encodes
+                .is_some_and(|max_encodes| running.encodes >= max_encodes)
+                || self
+                    .memory_budget
+                    .is_some_and(|budget| running.memory + memory > budget))
+        {
+            running = self.released.wait(running).unwrap();
+        }
+        running.encodes += 1;
+        running.memory += memory;
+    }
+
+    fn release(&self, memory: u64) {
+        let mut running = self.running.lock().unwrap();
+        running.encodes -= 1;
+        running.memory -= memory;
+        self.released.notify_all();
+    }
+}
+
+impl Encoder for LimitedEncoder {
+    fn encode(
+        &self,
+        image_path: &Path,
+        jxl_path: &Path,
+        image_format: ImageFormat,
+        cjxl_args: &JxlArgs,
+    ) -> Result<(), String> {
+        let memory = self
+            .memory_budget
+            .map(|_| estimate_memory(image_path))
+            .unwrap_or_default();
+        self.acquire(memory);
+        let result = self
+            .inner
+            .encode(image_path, jxl_path, image_format, cjxl_args);
+        self.release(memory);
+        result
+    }
+
+    fn version(&self) -> &str {
+        self.inner.version()
+    }
+}
+
+//헤더만 읽어서 크기를 알아냄. 못 읽으면 0 으로 보고 바로 돌림.
+fn estimate_memory(image_path: &Path) -> u64 {
+    let dimensions = ImageReader::open(image_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    match dimensions {
+        Some((width, height)) => {
+            let memory = width as u64 * height as u64 * BYTES_PER_PIXEL;
+            debug!(
+                "{}x{} needs about {} MB: {}",
+                width,
+                height,
+                memory >> 20,
+                image_path.display()
+            );
+            memory
+        }
+        None => 0,
+    }
+}
+
+//프로세스 우선순위를 낮춤. cjxl 자식 프로세스와 나중에 만드는 스레드도 따라감.
+//리눅스는 스레드마다 우선순위가 있으므로 다른 스레드를 만들기 전에 불러야 함.
+#[cfg(unix)]
+pub fn lower_priority() -> Result<(), String> {
+    //nice 10. 사용자가 다시 올릴 수는 없지만 끝나면 상관없음.
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, 10) };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn lower_priority() -> Result<(), String> {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS,
+    };
+    //BELOW_NORMAL 프로세스가 만든 자식 프로세스는 같은 우선순위로 시작함.
+    let result = unsafe { SetPriorityClass(GetCurrentProcess(), BELOW_NORMAL_PRIORITY_CLASS) };
+    if result == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn lower_priority() -> Result<(), String> {
+    Err("lowering priority is not supported on this platform".to_string())
+}
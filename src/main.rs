@@ -1,33 +1,59 @@
-use core::panic;
+mod archive;
+mod cli;
+mod comic;
+mod config;
+mod decoder;
+mod encoder;
+mod filter;
+mod format;
+mod interrupt;
+mod jxl;
+mod limits;
+mod metadata;
+mod plan;
+mod progress;
+mod repack;
+mod report;
+mod scheduler;
+mod worklist;
+
+use archive::{check_zip, find_zip_files, make_zip, ArchiveKind};
+use chrono::Local;
+use clap::{Parser, ValueEnum};
+use cli::{Cli, CommandKind};
+use config::{Config, FolderArgs, FolderConfig};
+use decoder::Decoder;
+use encoder::{Encoder, EncoderKind};
 use fern::Dispatch;
-use log::{info, warn};
+use filter::PathFilter;
+use format::SourceFormat;
+use image::io::Reader as ImageReader;
+use jwalk::{WalkDir, WalkDirGeneric};
+use log::{debug, info, warn};
+use metadata::MetadataKind;
+use progress::Progress;
 use rayon::prelude::*;
-use std::env;
+use report::{Deletion, Outcome, Report, Verification};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::fs::File;
+use std::hash::BuildHasherDefault;
 use std::io::Read;
 use std::io::{self, Write};
-use std::path::PathBuf;
-use std::process::Command;
-// use walkdir::DirEntry;
-use bincode;
-use chrono::Local;
-use hex;
-use image::io::Reader as ImageReader;
-use jwalk::WalkDirGeneric;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::error::Error;
-use std::ffi::OsStr;
-use std::hash::BuildHasherDefault;
-use trash;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use worklist::{EncodeSetting, FileState, FileStatus, WorkInfo, Worklist};
 use xxhash_rust::xxh3::xxh3_64;
 use xxhash_rust::xxh3::Xxh3;
 use zip::write::FileOptions;
 use zip::CompressionMethod::Stored;
-use zip::ZipWriter;
+use zip::ZipArchive;
 type XxHashMap<K, V> = HashMap<K, V, BuildHasherDefault<Xxh3>>;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 fn setup_logger() -> Result<(), fern::InitError> {
     let file_log = Dispatch::new()
         .format(|out, message, record| {
@@ -41,67 +67,21 @@ fn setup_logger() -> Result<(), fern::InitError> {
         .level(log::LevelFilter::Debug)
         .chain(fern::log_file("output.log")?);
 
+    //진행 줄을 지웠다가 다시 그리면서 찍음.
     let console_log = Dispatch::new()
         .level(log::LevelFilter::Info)
-        .chain(io::stdout());
+        .chain(fern::Output::call(progress::print_log));
 
     Dispatch::new().chain(file_log).chain(console_log).apply()?;
 
     Ok(())
 }
 
-fn make_zip(
-    folder_path: &PathBuf,
-    zip_options: FileOptions,
-    pack_files_list: Vec<PathBuf>,
-) -> Result<(), Box<dyn Error>> {
-    let zip_path = folder_path.with_extension("zip");
-    let mut zip = ZipWriter::new(File::create(&zip_path)?);
-
-    for pack_file in &pack_files_list {
-        // file_name = sub_entry.file_name().to_str().unwrap().to_string();
-        let f = File::open(pack_file);
-        let mut file = match f {
-            Ok(file) => file,
-            Err(err) => {
-                if err.kind() == std::io::ErrorKind::NotFound {
-                    warn!(
-                        "aleady delete. maybe duplicate file: {}",
-                        pack_file.display()
-                    );
-                    continue;
-                }
-                warn!("Failed to open file: {}", pack_file.display());
-                panic!("Failed to open file: {}", pack_file.display());
-            }
-        };
-
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer)?;
-        zip.start_file(
-            pack_file
-                .file_name()
-                .and_then(OsStr::to_str)
-                .unwrap_or_default(),
-            zip_options,
-        )?;
-        zip.write_all(&mut buffer)?;
-        fs::remove_file(&pack_file)?; //zip 파일에 추가한 jxl 파일을 삭제.
-    }
-    zip.finish()?;
-    //생성한 zip file이 비었다면 삭제.
-    if pack_files_list.is_empty() {
-        trash::delete(&zip_path)?;
-    }
-    Ok(())
-}
-
 fn image_to_jxl(
-    exe_path: &PathBuf,
+    encoder: &dyn Encoder,
+    cjxl_args: &JxlArgs,
     image_path: &PathBuf,
     jxl_path: &PathBuf,
-    png_args: &Vec<String>,
-    jpg_args: &Vec<String>,
     image_format: &image::ImageFormat,
 ) -> Result<(), String> {
     //jxl 파일이 이미 존재하면, 파일 해시 확인.
@@ -126,43 +106,18 @@ fn image_to_jxl(
         }
     }
 
-    let mut command = Command::new(exe_path);
-    command.args([image_path, &new_jxl_path]);
-    let output = match image_format {
-        image::ImageFormat::Jpeg => command.args(jpg_args).output().expect("Failed to convert"),
-        image::ImageFormat::Png => command.args(png_args).output().expect("Failed to convert"),
-        _ => {
-            return Err(format!(
-                "Failed file: {} \nerror message: Not supported file type",
-                image_path.display(),
-            ));
-        }
-    };
-
-    if !output.status.success() {
-        return Err(format!(
-            "Failed file: {} \nerror message: {}",
-            image_path.display(),
-            {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                format!("stdout: {}\nstderr: {}", stdout, stderr)
-            }
-        ));
-    }
+    encoder.encode(image_path, &new_jxl_path, *image_format, cjxl_args)?;
 
     //jxl 파일이 이미 존재하면, 파일 해시를 확인해 일치하는 파일은 삭제..
     if number > 0 {
         //let new_jxl_hash = finalize_md5(&new_jxl_path)?.finalize();
-        let new_jxl_hash = finalize_xxhash(&new_jxl_path)
-            .map_err(|err| {
-                format!(
-                    "Failed to finalize_xxhash: {}\nerror message: {}",
-                    new_jxl_path.display(),
-                    err
-                )
-            })
-            .unwrap();
+        let new_jxl_hash = finalize_xxhash(&new_jxl_path).map_err(|err| {
+            format!(
+                "Failed to finalize_xxhash: {}\nerror message: {}",
+                new_jxl_path.display(),
+                err
+            )
+        })?;
 
         for i in (0..number).rev() {
             let compare_path = if i == 0 {
@@ -177,15 +132,13 @@ fn image_to_jxl(
                     i
                 ))
             };
-            let compare_jxl_hash = finalize_xxhash(&compare_path)
-                .map_err(|err| {
-                    format!(
-                        "Failed to finalize_xxhash: {}\nerror message: {}",
-                        compare_path.display(),
-                        err
-                    )
-                })
-                .unwrap();
+            let compare_jxl_hash = finalize_xxhash(&compare_path).map_err(|err| {
+                format!(
+                    "Failed to finalize_xxhash: {}\nerror message: {}",
+                    compare_path.display(),
+                    err
+                )
+            })?;
 
             if new_jxl_hash != compare_jxl_hash {
                 warn!(
@@ -194,41 +147,60 @@ fn image_to_jxl(
             ); //파일 해시가 다르면, 파일을 삭제하지 않음.
             } else {
                 info!("file is same so delete: {}", &compare_path.display());
-                fs::remove_file(&compare_path).expect("Failed to delete file");
+                fs::remove_file(&compare_path).map_err(|err| {
+                    format!(
+                        "Failed to delete file: {}\nerror message: {}",
+                        compare_path.display(),
+                        err
+                    )
+                })?;
             }
         }
-        fs::rename(&new_jxl_path, jxl_path).expect("Failed to rename file");
+        fs::rename(&new_jxl_path, jxl_path).map_err(|err| {
+            format!(
+                "Failed to rename file: {}\nerror message: {}",
+                new_jxl_path.display(),
+                err
+            )
+        })?;
     }
 
     Ok(())
 }
 
 fn finalize_xxhash(file_path: &PathBuf) -> Result<u64, String> {
-    let mut file = File::open(file_path).expect("Failed to open file");
+    let mut file = File::open(file_path)
+        .map_err(|err| format!("Failed to open file: {}: {}", file_path.display(), err))?;
     let mut buffer = vec![];
-    file.read_to_end(&mut buffer).expect("Failed to read file");
+    file.read_to_end(&mut buffer)
+        .map_err(|err| format!("Failed to read file: {}: {}", file_path.display(), err))?;
     Ok(xxh3_64(&buffer))
 }
 
 fn is_image_file(path: &PathBuf) -> Result<image::ImageFormat, String> {
+    //확장자가 없거나 UTF-8 이 아닌 파일은 이미지로 보지 않음.
     let file_ext = path
         .extension()
-        .expect("hey, i don't get ext")
-        .to_str()
-        .expect("hey, i don't get ext str")
+        .and_then(|ext| ext.to_str())
+        .ok_or_else(|| format!("Failed file: {}\nWarn: No file extension", path.display()))?
         .to_lowercase();
 
-    if ["jpg", "png", "jpeg"].contains(&file_ext.as_str()) {
+    if SourceFormat::from_extension(&file_ext).is_some() {
         //이미지 포맷 확인
         let img_format = ImageReader::open(path)
-            .expect("Failed to open image file")
+            .map_err(|err| format!("Failed file: {}\nerror message: {}", path.display(), err))?
             .format()
-            .expect("Failed to read image format");
+            .ok_or_else(|| {
+                format!(
+                    "Failed file: {}\nWarn: Unknown image format",
+                    path.display()
+                )
+            })?;
 
-        match img_format {
-            image::ImageFormat::Png | image::ImageFormat::Jpeg => Ok(img_format),
-            // Add more formats as needed
-            _ => Err(format!(
+        match SourceFormat::from_image_format(img_format) {
+            Some(_) => Ok(img_format),
+            // Add more formats in format.rs
+            None => Err(format!(
                 "Failed file: {}\nWarn: This file is skip",
                 path.display(),
             )), //"The image is current not support format"
@@ -245,351 +217,1651 @@ fn is_image_file(path: &PathBuf) -> Result<image::ImageFormat, String> {
         )) //"The file is not image"
     }
 }
+//인코더가 jxl 옆에 잠깐 만드는 파일. 중간에 꺼지면 남을 수 있음.
+//jxl_check 는 이전 버전의 djxl 확인이 jxl 옆에 남긴 파일.
+fn temp_files(jxl_path: &Path) -> [PathBuf; 3] {
+    [
+        jxl_path.with_extension("jxl_tmp.png"),
+        jxl_path.with_extension("jxl_check.jpg"),
+        jxl_path.with_extension("jxl_check.png"),
+    ]
+}
+
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.contains(".jxl_tmp.") || name.contains(".jxl_check."))
+}
+
 //TODO
 //1. 따옴표가 없는 줄도 경로 알아채기. - 보류.
 //2. 폴더 스캔에 걸리는 시간 측정. - Done.
 //3. 작업 내역을 생성 및 저장 및 읽기로 이미 작업을 마친 폴더는 건너뛰기. - Done.
 //4. md5 해시가 아닌, xxhash로 이미지 해시값 생성. - Done.
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<ExitCode, Box<dyn Error>> {
     setup_logger()?;
-    //cjxl_args 불러오기.
-    let cjxl_args = match read_cjxl_args() {
-        Ok(jxl_args) => {
-            if jxl_args.png_args.is_empty() || jxl_args.jpg_args.is_empty() {
-                //cjxl_args.ini가 비어있으면, 프로그램 종료.
-                warn!("cjxl_args.ini is empty");
-                panic!("cjxl_args.ini is empty");
-            }
-            jxl_args
-        }
-        Err(_) => {
-            let mut text_file = File::create("cjxl_args.ini")?;
-            text_file.write_all(b"delete_folder=false\ndelete_source_image=false\nmake_zip=true\ndont_use_trashcan_just_delete=false\npng_args=[--distance=0,--effort=7]\njpg_args=[--distance=0,--effort=9,--lossless_jpeg=1]")?;
-
-            warn!("Failed to read cjxl_args.ini");
-            let mut jxl_args = JxlArgs::default();
-            jxl_args.png_args = vec!["--distance=0".to_string(), "--effort=7".to_string()];
-            jxl_args.jpg_args = vec![
-                "--distance=0".to_string(),
-                "--effort=9".to_string(),
-                "--lossless_jpeg=1".to_string(),
-            ];
-            jxl_args
+    let (command, work_args) = Cli::parse().into_command();
+    interrupt::install()?;
+
+    //--plan-json 도 dry run.
+    let dry_run = work_args.dry_run || work_args.plan_json.is_some();
+    if dry_run && matches!(command, CommandKind::Unpack | CommandKind::Repack) {
+        warn!("--dry-run is not supported for unpack and repack");
+        return Ok(ExitCode::from(2));
+    }
+
+    //설정 불러오기. 작업 폴더의 .jxlzip.toml 은 작업 폴더마다 더함.
+    //dry run 은 기본 설정 파일도 만들지 않음.
+    let config = match Config::load(work_args.config.as_deref(), dry_run) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("{}", err);
+            return Ok(ExitCode::from(2));
         }
     };
-
-    info!(
-        "{}",
-        format!(
-            r#"cjxl_args:
-            delete_folder={}
-            delete_source_image={}
-            make_zip={}
-            dont_use_trashcan_just_delete={}
-            png_args={:?}
-            jpg_args={:?}"#,
-            cjxl_args.delete_folder_plag,
-            cjxl_args.delete_source_image_plag,
-            cjxl_args.make_zip_plag,
-            cjxl_args.dont_use_trashcan_just_delete,
-            cjxl_args.png_args,
-            cjxl_args.jpg_args
-        )
+    let base_args = match config.jxl_args(work_args.profile.as_deref()) {
+        Ok(base_args) => base_args,
+        Err(err) => {
+            warn!("{}", err);
+            return Ok(ExitCode::from(2));
+        }
+    };
+    let folder_config = FolderConfig::new(
+        &config,
+        base_args,
+        work_args.profile.clone(),
+        work_args.overrides.clone(),
     );
+    let global_args = match resolve_jxl_args(&config, &folder_config) {
+        Ok(cjxl_args) => cjxl_args,
+        Err(err) => {
+            warn!("{}", err);
+            return Ok(ExitCode::from(2));
+        }
+    };
+
+    let mut work_folders = work_args.folders.clone();
+    if work_folders.is_empty() {
+        if work_args.non_interactive {
+            warn!("No work folder given");
+            return Ok(ExitCode::from(2));
+        }
+        work_folders.push(read_work_folder_from_stdin()?);
+    }
 
-    //let current_dir = env::current_dir().unwrap();
-    let exe_path = env::current_dir()?.join("cjxl.exe");
-    if !exe_path.exists() {
-        warn!("cjxl.exe not exists");
-        panic!("cjxl.exe not exists");
+    //다른 스레드와 cjxl 을 만들기 전에 낮춰야 모두 따라감.
+    //프로세스에 하나뿐이므로 작업 폴더 하나라도 low_priority 면 처음부터 낮춤.
+    let low_priority =
+        work_folders.iter().any(
+            |work_folder| match folder_config.work_folder_args(work_folder) {
+                Ok(folder_args) => folder_args.args.low_priority,
+                Err(_) => global_args.low_priority,
+            },
+        );
+    if low_priority {
+        if let Err(err) = limits::lower_priority() {
+            warn!("Failed to lower priority: {}", err);
+        }
     }
-    info!("current cjxl.exe location: {:?}", exe_path);
-    let begin_args: Vec<String> = std::env::args().collect();
-    let path_pattern = ['"', '\'']; //윈도우에서는 "로 경로를 감싸는 경우가 많아서, "를 제거함. 따옴표도 제거.
-    let folder_path_input = if begin_args.len() > 1 {
-        begin_args[1].clone() // 첫번째 인자로 폴더 경로를 받음.
-    } else {
-        info!("Drag&Drop folder to convert jxl and changed zip you want: ");
-        let mut user_input = String::new();
-        io::stdout().flush()?;
-
-        loop {
-            io::stdin().read_line(&mut user_input)?;
-            if user_input.is_empty() {
-                warn!("Folder path is empty. Please enter again: ");
+
+    //실패한 작업 폴더 수. 하나라도 있으면 종료 코드 1.
+    let mut failed_work_folders = 0;
+    let mut plans = vec![];
+    let report = Report::default();
+    for work_folder in &work_folders {
+        if interrupt::interrupted() {
+            break;
+        }
+        if !work_folder.is_dir() {
+            warn!("Folder path is not valid: {}", work_folder.display());
+            failed_work_folders += 1;
+            continue;
+        }
+
+        let cjxl_args = match folder_config.work_folder_args(work_folder) {
+            Ok(folder_args) => (*folder_args.args).clone(),
+            Err(err) => {
+                warn!(
+                    "Failed work folder: {}\nerror message: {}",
+                    work_folder.display(),
+                    err
+                );
+                failed_work_folders += 1;
                 continue;
             }
+        };
+        log_cjxl_args(&cjxl_args);
 
-            //let folder_path_input = user_input.trim().trim_matches(path_pattern).to_string();
-            let folder_path_input = path_pattern.into_iter().find_map(|c| {
-                let split: Vec<&str> = user_input.split(c).collect();
-                if split.len() > 1 && !split[1].is_empty() {
-                    Some(split[1].to_string())
-                } else {
+        let encoder = if matches!(command, CommandKind::Convert | CommandKind::Repack) {
+            match encoder::build_encoder(&cjxl_args) {
+                Ok(encoder) => Some(encoder),
+                Err(err) => {
+                    warn!("{}", err);
+                    return Ok(ExitCode::from(2));
+                }
+            }
+        } else {
+            None
+        };
+        //원본을 지우기 전에 jxl 을 다시 풀어서 확인할 디코더.
+        let decoder = if matches!(command, CommandKind::Convert | CommandKind::Repack)
+            && cjxl_args.verify_image
+        {
+            match decoder::build_decoder(&cjxl_args) {
+                Ok(decoder) => Some(decoder),
+                Err(err) => {
+                    warn!("{}", err);
+                    return Ok(ExitCode::from(2));
+                }
+            }
+        } else if command == CommandKind::Unpack {
+            //디코더가 없으면 zip 만 풀고 jxl 은 그대로 둠.
+            match decoder::build_decoder(&cjxl_args) {
+                Ok(decoder) => Some(decoder),
+                Err(err) => {
+                    warn!("{}\njxl files are left as they are", err);
                     None
                 }
-            });
-            match folder_path_input {
-                Some(path) if PathBuf::from(&path).is_dir() => {
-                    break path;
+            }
+        } else {
+            None
+        };
+
+        //dry run 은 계획만 세우고 아무것도 바꾸지 않음.
+        if dry_run && matches!(command, CommandKind::Convert | CommandKind::Zip) {
+            let plan = match command {
+                CommandKind::Convert => plan::plan_convert(
+                    work_folder,
+                    &cjxl_args,
+                    &folder_config,
+                    encoder
+                        .as_deref()
+                        .expect("encoder is built for convert")
+                        .version(),
+                    work_args.reencode_stale,
+                ),
+                _ => plan::plan_zip(work_folder, &cjxl_args, &folder_config),
+            };
+            match plan {
+                Ok(plan) => {
+                    plan.print();
+                    plans.push(plan);
                 }
-                _ => {
-                    warn!("Folder path is not valid. Please enter again: ");
-                    user_input.clear();
+                Err(err) => {
+                    warn!(
+                        "Failed work folder: {}\nerror message: {}",
+                        work_folder.display(),
+                        err
+                    );
+                    failed_work_folders += 1;
                 }
             }
+            continue;
         }
-    };
 
-    let work_folder_hex = hex::encode(xxh3_64(folder_path_input.as_bytes()).to_be_bytes());
-
-    //작업 내역이 있으면, 작업 내역을 불러옴.
-    let mut work_info = match File::open(format!("{}.bin", work_folder_hex)) {
-        Ok(file) => {
-            info!("Worklist file exists so load worklist.");
-            bincode::deserialize_from(file).expect("Failed to load worklist")
-        }
-        Err(_) => {
-            info!("No worklist file so create new worklist.");
-            WorkInfo::new(folder_path_input.clone(), cjxl_args.clone())
+        let result = match command {
+            CommandKind::Convert => convert_work_folder(
+                work_folder,
+                &cjxl_args,
+                &folder_config,
+                encoder.as_deref().expect("encoder is built for convert"),
+                decoder.as_deref(),
+                work_args.reencode_stale,
+                &report,
+            ),
+            CommandKind::Zip => zip_work_folder(work_folder, &cjxl_args, &folder_config),
+            CommandKind::Verify => verify_work_folder(work_folder, &cjxl_args, &folder_config),
+            CommandKind::Unpack => {
+                unpack_work_folder(work_folder, &cjxl_args, &folder_config, decoder.as_deref())
+            }
+            CommandKind::Repack => repack::repack_work_folder(
+                work_folder,
+                &cjxl_args,
+                &folder_config,
+                encoder.as_deref().expect("encoder is built for repack"),
+                decoder.as_deref(),
+                &report,
+            ),
+            CommandKind::Status => show_status(work_folder, &cjxl_args, &folder_config),
+        };
+        match result {
+            Ok(0) => {}
+            Ok(failed_folders) => {
+                warn!("{} failed in {}", failed_folders, work_folder.display());
+                failed_work_folders += 1;
+            }
+            Err(err) => {
+                warn!(
+                    "Failed work folder: {}\nerror message: {}",
+                    work_folder.display(),
+                    err
+                );
+                failed_work_folders += 1;
+            }
         }
-    };
+    }
 
-    //작업 시간 측정
-    let start = Instant::now();
+    if let Some(plan_path) = &work_args.plan_json {
+        serde_json::to_writer_pretty(File::create(plan_path)?, &plans)?;
+        info!("Plan written: {}", plan_path.display());
+    }
 
-    //폴더 경로를 가져옴
-    // let folder_path_input = user_input.trim().split('"').nth(1).unwrap_or_default();
-    //폴더 경로를 PathBuf로 변환
-    // let mother_folder_path = PathBuf::from(user_input.trim().split('"').collect::<Vec<&str>>()[1]);
-
-    // ...
-    let mut folder_list = WalkDirGeneric::<(usize, bool)>::new(&folder_path_input)
-        .process_read_dir(|depth, path, read_dir_state, children| {
-            //작업 내역이 있으면, 작업 내역을 불러옴.
-            children.retain(|dir_entry_result| {
-                dir_entry_result.as_ref().map_or(false, |dir_entry| {
-                    if dir_entry.path().is_dir() {
-                        true
-                    } else {
-                        false
-                    }
-                })
-            });
-        })
-        .into_iter()
-        .filter_map(|dir_result| match dir_result {
-            Ok(entry) => {
-                let path = entry.path();
-                match work_info.worklist.get(&path) {
-                    Some(&true) => {
-                        info!("Already done: {}", path.display());
-                        None
-                    }
-                    Some(&false) => Some(path),
-                    None => {
-                        work_info.worklist.insert(path.clone(), false);
-                        Some(path)
-                    }
-                }
+    //--report 는 convert 의 결과와 repack 이 건너뛴 이미지만 씀.
+    if matches!(command, CommandKind::Convert | CommandKind::Repack) && !dry_run {
+        for report_path in &work_args.report {
+            match report.write(report_path) {
+                Ok(()) => info!("Report written: {}", report_path.display()),
+                Err(err) => warn!(
+                    "Failed to write report: {}\nerror message: {}",
+                    report_path.display(),
+                    err
+                ),
             }
-            Err(_) => None,
-        })
-        .collect::<Vec<PathBuf>>();
+        }
+    } else if !work_args.report.is_empty() {
+        warn!("--report is only written by convert and repack");
+    }
 
-    let file_search_duration = start.elapsed();
-    let (fs_hours, fs_minutes, fs_seconds, fs_milliseconds) = time_display(file_search_duration);
-    info!(
-        "File search time:{:02}:{:02}:{:02}:{:03}",
-        fs_hours, fs_minutes, fs_seconds, fs_milliseconds
-    );
+    if interrupt::interrupted() {
+        warn!("Interrupted. run again to continue");
+        return Ok(ExitCode::from(interrupt::EXIT_CODE));
+    }
 
-    //하위 폴더부터 변환하기 위해 폴더 리스트를 정렬.
-    folder_list.sort_by(|a, b| {
-        //components().count()는 폴더의 깊이를 나타냄.
-        b.components().count().cmp(&a.components().count())
-    });
+    if !work_args.non_interactive {
+        println!("Press Enter to exit...");
+        io::stdin().read_line(&mut String::new())?;
+    }
 
-    let zip_options = FileOptions::default()
-        .compression_method(Stored)
-        .unix_permissions(0o755);
+    if failed_work_folders > 0 {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
 
-    //if folder not in imagefile, then skip
-    //폴더 안에 이미지 파일을 찾아서 jxl로 변환하는 놈
-    for folder_path in folder_list {
-        info!("folder_path: {}", folder_path.display());
+fn read_work_folder_from_stdin() -> Result<PathBuf, Box<dyn Error>> {
+    let path_pattern = ['"', '\'']; //윈도우에서는 "로 경로를 감싸는 경우가 많아서, "를 제거함. 따옴표도 제거.
+    info!("Drag&Drop folder to convert jxl and changed zip you want: ");
+    let mut user_input = String::new();
+    io::stdout().flush()?;
 
-        let mut delete_folder_plag = cjxl_args.delete_folder_plag;
-        let mut can_i_make_zip_file = cjxl_args.make_zip_plag;
+    loop {
+        if io::stdin().read_line(&mut user_input)? == 0 {
+            //stdin이 닫히면 더 이상 기다리지 않음.
+            return Err("stdin closed before a folder path was given".into());
+        }
+        if user_input.trim().is_empty() {
+            warn!("Folder path is empty. Please enter again: ");
+            user_input.clear();
+            continue;
+        }
 
-        let pack_files_list: Vec<Result<JXL, String>> = PathBuf::from(&folder_path)
-            .read_dir()?
-            .filter_map(Result::ok)
-            .filter(|entry| entry.path().is_file() && entry.path().extension().unwrap() != "zip")
-            .collect::<Vec<_>>()
-            .par_iter()
-            .map(|entry| match is_image_file(&entry.path()) {
-                Ok(image_format) => {
-                    let jxl_path = entry.path().with_extension("jxl");
-                    match image_to_jxl(
-                        &exe_path,
-                        &entry.path(),
-                        &jxl_path,
-                        &cjxl_args.png_args,
-                        &cjxl_args.jpg_args,
-                        &image_format,
-                    ) {
-                        Ok(_) => {
-                            if cjxl_args.delete_source_image_plag {
-                                //원본 파일 삭제.
-                                if cjxl_args.dont_use_trashcan_just_delete {
-                                    fs::remove_file(&entry.path()).expect("Failed to delete file");
-                                } else {
-                                    trash::delete(&entry.path()).expect("Failed to delete file33");
-                                }
-                            }
-                            Ok(JXL::Converted(jxl_path))
-                        }
-                        Err(err) => Err(err),
+        //let folder_path_input = user_input.trim().trim_matches(path_pattern).to_string();
+        let folder_path_input = path_pattern.into_iter().find_map(|c| {
+            let split: Vec<&str> = user_input.split(c).collect();
+            if split.len() > 1 && !split[1].is_empty() {
+                Some(split[1].to_string())
+            } else {
+                None
+            }
+        });
+        match folder_path_input {
+            Some(path) if PathBuf::from(&path).is_dir() => {
+                break Ok(PathBuf::from(path));
+            }
+            _ => {
+                warn!("Folder path is not valid. Please enter again: ");
+                user_input.clear();
+            }
+        }
+    }
+}
+
+//폴더를 읽을 때 하위 폴더에 넘기는 상태. 하위 폴더는 이것을 복사해서 받음.
+#[derive(Clone, Default, Debug)]
+struct ScanState {
+    //읽는 폴더의 설정.
+    folder_args: Option<FolderArgs>,
+    //읽는 폴더나 그 위 폴더가 include_folders 에 맞는지.
+    included: bool,
+    //하위 폴더 중 .jxlzip.toml 이 있거나 include_folders 에 새로 맞는 폴더의 상태.
+    child_states: HashMap<OsString, (FolderArgs, bool)>,
+}
+
+//작업 폴더 아래의 모든 폴더를 찾아, 하위 폴더부터 정렬해서 돌려줌.
+//폴더마다 .jxlzip.toml 을 위 폴더의 설정 위에 더한 설정을 함께 돌려줌. 하나라도 읽지 못하면 에러.
+//exclude_folders 에 맞거나 .nojxl 이 있는 폴더는 하위 폴더까지 읽지 않고, include_folders 에 맞지 않는 폴더는 빼고 돌려줌.
+fn scan_folders(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+) -> Result<Vec<(PathBuf, Arc<JxlArgs>)>, String> {
+    if let Some(reason) = cjxl_args.filter.skip_folder(work_folder, work_folder) {
+        warn!("Skip work folder {} ({})", work_folder.display(), reason);
+        return Ok(vec![]);
+    }
+    //하위 폴더는 작업 폴더의 merged 위에 자기 .jxlzip.toml 을 더함.
+    let work_args = folder_config.work_folder_args(work_folder)?;
+    let work_included = cjxl_args.filter.includes_folder(work_folder, work_folder);
+    let folder_config = folder_config.clone();
+    let errors = Arc::new(Mutex::new(vec![]));
+    let scan_errors = errors.clone();
+    let root_args = work_args.clone();
+    let root_folder = work_folder.to_path_buf();
+    let mut folder_list =
+        WalkDirGeneric::<(ScanState, Option<(FolderArgs, bool)>)>::new(work_folder)
+            .root_read_dir_state(ScanState {
+                folder_args: Some(work_args.clone()),
+                included: work_included,
+                child_states: HashMap::new(),
+            })
+            .process_read_dir(move |depth, path, state, children| {
+                children.retain(|dir_entry_result| {
+                    dir_entry_result
+                        .as_ref()
+                        .is_ok_and(|dir_entry| dir_entry.path().is_dir())
+                });
+                //작업 폴더 자신. 작업 폴더의 .jxlzip.toml 은 이미 work_args 에 들어있음.
+                if depth.is_none() {
+                    for dir_entry in children.iter_mut().flatten() {
+                        dir_entry.client_state = state
+                            .folder_args
+                            .clone()
+                            .map(|folder_args| (folder_args, state.included));
                     }
+                    return;
                 }
-                Err(err) => {
-                    if err.contains("This file is skip") {
-                        Ok(JXL::ExistFromBegin(entry.path()))
-                    } else {
-                        Err(err)
+                if let Some((folder_args, included)) = path
+                    .file_name()
+                    .and_then(|name| state.child_states.get(name))
+                {
+                    state.folder_args = Some(folder_args.clone());
+                    state.included = *included;
+                }
+                state.child_states.clear();
+                let parent_args = state
+                    .folder_args
+                    .clone()
+                    .unwrap_or_else(|| root_args.clone());
+                //건너뛸 폴더는 목록에서 빼서 jwalk 가 읽지 않게 함.
+                children.retain(|dir_entry_result| {
+                    let Ok(dir_entry) = dir_entry_result else {
+                        return true;
+                    };
+                    match parent_args
+                        .args
+                        .filter
+                        .skip_folder(&root_folder, &dir_entry.path())
+                    {
+                        Some(reason) => {
+                            info!("Skip folder {} ({})", dir_entry.path().display(), reason);
+                            false
+                        }
+                        None => true,
                     }
+                });
+                for dir_entry in children.iter_mut().flatten() {
+                    let child_path = dir_entry.path();
+                    let included = state.included
+                        || parent_args
+                            .args
+                            .filter
+                            .includes_folder(&root_folder, &child_path);
+                    let folder_args =
+                        match folder_config.subfolder_args(&parent_args, &root_args, &child_path) {
+                            Ok(Some(folder_args)) => Some(folder_args),
+                            Ok(None) => None,
+                            Err(err) => {
+                                scan_errors.lock().expect("scan errors lock").push(err);
+                                None
+                            }
+                        };
+                    if folder_args.is_some() || included != state.included {
+                        let folder_args =
+                            folder_args.clone().unwrap_or_else(|| parent_args.clone());
+                        state
+                            .child_states
+                            .insert(dir_entry.file_name().to_owned(), (folder_args, included));
+                    }
+                    let folder_args = folder_args.unwrap_or_else(|| parent_args.clone());
+                    dir_entry.client_state = Some((folder_args, included));
                 }
             })
-            .collect();
+            .into_iter()
+            .filter_map(|dir_result| {
+                dir_result.ok().and_then(|entry| {
+                    let (folder_args, included) = entry
+                        .client_state
+                        .clone()
+                        .unwrap_or_else(|| (work_args.clone(), work_included));
+                    included.then(|| (entry.path(), folder_args.args))
+                })
+            })
+            .collect::<Vec<(PathBuf, Arc<JxlArgs>)>>();
+    let errors = errors.lock().expect("scan errors lock");
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
 
-        if pack_files_list.is_empty() {
-            info!("No image file in folder");
-            work_info.update_list_element(&folder_path);
-            continue;
-        }
+    //하위 폴더부터 변환하기 위해 폴더 리스트를 정렬.
+    folder_list.sort_by(|(a, _), (b, _)| {
+        //components().count()는 폴더의 깊이를 나타냄.
+        b.components().count().cmp(&a.components().count())
+    });
+    Ok(folder_list)
+}
 
-        //error check
-        for pack_file in &pack_files_list {
-            if let Err(err) = pack_file {
-                warn!("{}\npass this folder", err);
-                can_i_make_zip_file = false;
-                delete_folder_plag = false;
-                break;
-            }
+fn has_source_image(folder_path: &Path) -> bool {
+    folder_path.read_dir().is_ok_and(|read_dir| {
+        read_dir.filter_map(Result::ok).any(|entry| {
+            let path = entry.path();
+            !is_temp_file(&path)
+                && path
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .and_then(SourceFormat::from_extension)
+                    .is_some()
+        })
+    })
+}
+
+//변환하지 않고 건너뛸 원본이면 그 이유. 원본 이미지가 아닌 파일은 None.
+//경로 필터, 크기, 수정 시각, 픽셀 수 순서로 봄. 픽셀 수는 헤더만 읽음.
+fn skip_reason(work_folder: &Path, cjxl_args: &JxlArgs, path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .and_then(SourceFormat::from_extension)?;
+    if let Some(reason) = cjxl_args.filter.skip_file(work_folder, path) {
+        return Some(reason);
+    }
+    if let Ok(metadata) = path.metadata() {
+        if let Some(reason) = size_age_reason(cjxl_args, metadata.len(), metadata.modified().ok()) {
+            return Some(reason);
         }
+    }
+    pixels_reason(cjxl_args, path)
+}
 
-        if !can_i_make_zip_file {
-            //파일 하나라도 이미지 변환에 실패하는 경우, zip 파일을 만들지 않음.
-            //또는 사용자가 zip 파일을 만들지 않겠다고 설정한 경우.
-            info!("Do not make zip file");
-            work_info.update_list_element(&folder_path);
-            continue;
+//min_bytes, min_age_days 에 걸리면 그 이유. zip 안의 이미지는 항목의 크기와 시각으로 봄.
+fn size_age_reason(
+    cjxl_args: &JxlArgs,
+    bytes: u64,
+    modified: Option<SystemTime>,
+) -> Option<String> {
+    if bytes < cjxl_args.min_bytes {
+        return Some(format!(
+            "{} bytes is less than min_bytes {}",
+            bytes, cjxl_args.min_bytes
+        ));
+    }
+    if cjxl_args.min_age_days > 0 {
+        //미래 시각이면 방금 수정한 것으로 봄.
+        let age = modified
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if age < Duration::from_secs(cjxl_args.min_age_days * 86_400) {
+            return Some(format!(
+                "modified {} hours ago, within min_age_days {}",
+                age.as_secs() / 3_600,
+                cjxl_args.min_age_days
+            ));
         }
+    }
+    None
+}
 
-        //zip 파일 만들기 - png, jpg를 포함한 모든 이미지 파일을 zip으로 묶음.
-        let pack_files_list: Vec<_> = pack_files_list
-            .into_iter()
-            .filter_map({
-                |pack_file| match pack_file {
-                    Ok(JXL::Converted(jxl_path)) => Some(jxl_path),
-                    Ok(JXL::ExistFromBegin(jxl_path)) => Some(jxl_path),
-                    Err(err) => {
-                        //not use this line, but still keep it.
-                        warn!("{}\npass this folder", err);
-                        None
-                    }
-                }
-            })
-            .collect();
-        let _ = make_zip(&folder_path, zip_options, pack_files_list);
+//max_pixels 에 걸리면 그 이유. 헤더만 읽음.
+fn pixels_reason(cjxl_args: &JxlArgs, path: &Path) -> Option<String> {
+    if cjxl_args.max_pixels > 0 {
+        //image 가 읽지 못하는 포맷은 cjxl 에 맡김.
+        let dimensions = ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok());
+        if let Some((width, height)) = dimensions {
+            if width as u64 * height as u64 > cjxl_args.max_pixels {
+                return Some(format!(
+                    "{}x{} is more than max_pixels {}",
+                    width, height, cjxl_args.max_pixels
+                ));
+            }
+        }
+    }
+    None
+}
 
-        if delete_folder_plag {
-            //폴더 삭제. 만약 삭제하려는 폴더 안에 다른 폴더, 이미지가 아닌 파일이 있으면 폴더를 삭제하지 않음.
-            if cjxl_args.dont_use_trashcan_just_delete {
-                fs::remove_dir_all(&folder_path)?;
+//폴더 아래에서 scan_folders 가 건너뛴 폴더 하나. 건너뛴 폴더 안은 읽지 않음.
+fn skipped_folder(folder_path: &Path, scanned: &HashSet<PathBuf>) -> Option<PathBuf> {
+    folder_path
+        .read_dir()
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .find_map(|path| {
+            if scanned.contains(&path) {
+                skipped_folder(&path, scanned)
             } else {
-                trash::delete(&folder_path)?;
+                Some(path)
             }
-        }
+        })
+}
 
-        //work_info.worklist[&folder_path] = true;//why rust not allow IndexMut????
-        //work_info 업데이트
-        work_info.update_list_element(&folder_path);
+//zip_depth 에 있는 조상 폴더. zip_depth 보다 얕은 폴더는 None 이고, 폴더마다 zip 을 만듦.
+fn archive_root(work_folder: &Path, folder_path: &Path, zip_depth: usize) -> Option<PathBuf> {
+    let relative = folder_path.strip_prefix(work_folder).ok()?;
+    if relative.components().count() < zip_depth {
+        return None;
     }
+    Some(work_folder.join(relative.components().take(zip_depth).collect::<PathBuf>()))
+}
 
-    //작업 리스트 저장.
-    let mut file = File::create(format!("{}.bin", work_folder_hex))?;
-    bincode::serialize_into(&mut file, &work_info)?;
+//하나의 zip 으로 묶을 폴더들. 하위 폴더부터 처리하므로 archive_root 가 가장 마지막에 옴.
+#[derive(Default)]
+struct ArchiveGroup {
+    files: Vec<PathBuf>,
+    //변환한 원본 이미지. zip 을 만들면 Packed 로 기록함.
+    sources: Vec<PathBuf>,
+    //jxl 대신 zip 에 넣은 원본.
+    source_files: Vec<PathBuf>,
+    //폴더와, 그 폴더에서 변환한 이미지가 있으면 그 설정의 id.
+    folders: Vec<(PathBuf, Option<u64>)>,
+    failed: bool,
+    //건너뛴 원본이 있으면 archive_root 를 지우지 않음.
+    skipped: bool,
+}
 
-    info!("All done!");
-    let duration_time = start.elapsed();
-    let (hours, minutes, seconds, milliseconds) = time_display(duration_time);
-    info!(
-        "Duration time:{:02}:{:02}:{:02}.{:03}",
-        hours, minutes, seconds, milliseconds
-    );
-    info!(
-        "File search time:{:02}:{:02}:{:02}.{:03}",
-        fs_hours, fs_minutes, fs_seconds, fs_milliseconds
-    );
+fn delete_path(path: &Path, cjxl_args: &JxlArgs) -> Result<(), Box<dyn Error>> {
+    if cjxl_args.dont_use_trashcan_just_delete {
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    } else {
+        trash::delete(path)?;
+    }
+    Ok(())
+}
 
-    println!("Press Enter to exit...");
-    io::stdin().read_line(&mut String::new())?;
+//jxl 대신 zip 에 넣은 원본을 delete_source_image 에 따라 지움.
+fn delete_packed_sources(
+    source_files: &[PathBuf],
+    cjxl_args: &JxlArgs,
+    report: &Report,
+) -> Result<(), Box<dyn Error>> {
+    if !cjxl_args.delete_source_image_plag {
+        return Ok(());
+    }
+    for source_file in source_files {
+        delete_path(source_file, cjxl_args)?;
+        report.update(source_file, |row| {
+            row.deletion = Some(cjxl_args.deletion());
+        });
+    }
     Ok(())
 }
 
-fn read_cjxl_args() -> Result<JxlArgs, String> {
-    let content = fs::read_to_string("cjxl_args.ini");
-    let args_pattern: &[_] = &['[', ']'];
-    let mut jxlargs = JxlArgs {
-        delete_folder_plag: false,
-        delete_source_image_plag: false,
-        make_zip_plag: true,
-        dont_use_trashcan_just_delete: false,
-        png_args: vec![],
-        jpg_args: vec![],
+//작업 폴더 하나를 변환. 실패한 폴더 수를 돌려줌.
+fn convert_work_folder(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+    encoder: &dyn Encoder,
+    decoder: Option<&dyn Decoder>,
+    reencode_stale: bool,
+    report: &Report,
+) -> Result<usize, Box<dyn Error>> {
+    let encoder_version = encoder.version();
+
+    //작업 내역이 있으면, 작업 내역을 불러옴. 지난번에 멈춘 곳까지의 journal 도 함께 읽음.
+    let worklist = Worklist::open(work_folder, cjxl_args, encoder_version)?;
+    {
+        let mut work_info = worklist.info();
+        if work_info.encoder_version != encoder_version {
+            info!(
+                "cjxl version changed: {} -> {}",
+                work_info.encoder_version, encoder_version
+            );
+            work_info.encoder_version = encoder_version.to_string();
+        }
+    }
+
+    //작업 시간 측정
+    let start = Instant::now();
+
+    //폴더마다의 설정. .jxlzip.toml 이 있는 폴더와 그 아래 폴더는 작업 폴더와 다름.
+    let scanned_folders = scan_folders(work_folder, cjxl_args, folder_config)?;
+    let mut setting_ids = BTreeSet::new();
+    for (folder_path, folder_args) in &scanned_folders {
+        worklist.set_folder_args(folder_path, folder_args)?;
+        setting_ids.insert(worklist.add_setting(folder_args)?);
+    }
+    let folder_args: HashMap<PathBuf, Arc<JxlArgs>> = scanned_folders.iter().cloned().collect();
+    let scanned: HashSet<PathBuf> = folder_args.keys().cloned().collect();
+    let args_of = |folder_path: &Path| -> &JxlArgs {
+        folder_args
+            .get(folder_path)
+            .map(Arc::as_ref)
+            .unwrap_or(cjxl_args)
     };
-    match content {
-        Ok(file) => {
-            for line in file.lines() {
-                let arg = line.trim();
-                if let Some(args_str) = arg.strip_prefix("delete_folder=") {
-                    if args_str.to_lowercase() == "true" {
-                        jxlargs.delete_folder_plag = true;
-                    }
-                } else if let Some(args_str) = arg.strip_prefix("delete_source_image=") {
-                    if args_str.to_lowercase() == "true" {
-                        jxlargs.delete_source_image_plag = true;
-                    }
-                } else if let Some(args_str) = arg.strip_prefix("make_zip=") {
-                    if args_str.to_lowercase() == "false" {
-                        jxlargs.make_zip_plag = false;
+
+    //지금과 다른 설정으로 끝낸 폴더.
+    let stale_folders = {
+        let work_info = worklist.info();
+        work_info.report_settings(&setting_ids);
+        work_info.stale_folders(|path| worklist.setting_id(args_of(path)))
+    };
+    if !stale_folders.is_empty() && !reencode_stale {
+        warn!(
+            "{} folders were made with other settings. run with --reencode-stale to convert them again",
+            stale_folders.len()
+        );
+    }
+
+    let folder_list = {
+        let mut work_info = worklist.info();
+        scanned_folders
+            .into_iter()
+            .map(|(path, _)| path)
+            .filter(|path| match work_info.worklist.get(path) {
+                Some(&true) if reencode_stale && stale_folders.binary_search(path).is_ok() => {
+                    //원본이 남아있어야 다시 변환할 수 있음.
+                    if has_source_image(path) {
+                        info!("Stale so convert again: {}", path.display());
+                        work_info.worklist.insert(path.clone(), false);
+                        work_info.reencoded.insert(path.clone());
+                        true
+                    } else {
+                        warn!("Stale but no source image left: {}", path.display());
+                        false
                     }
-                } else if let Some(args_str) = arg.strip_prefix("dont_use_trashcan_just_delete=") {
-                    if args_str.to_lowercase() == "true" {
-                        jxlargs.dont_use_trashcan_just_delete = true;
+                }
+                Some(&true) => {
+                    info!("Already done: {}", path.display());
+                    false
+                }
+                Some(&false) => true,
+                None => {
+                    work_info.worklist.insert(path.clone(), false);
+                    true
+                }
+            })
+            .collect::<Vec<PathBuf>>()
+    };
+
+    let file_search_duration = start.elapsed();
+    let (fs_hours, fs_minutes, fs_seconds, fs_milliseconds) = time_display(file_search_duration);
+    info!(
+        "File search time:{:02}:{:02}:{:02}:{:03}",
+        fs_hours, fs_minutes, fs_seconds, fs_milliseconds
+    );
+
+    //폴더마다 변환할 파일. 진행 상황의 합계도 여기서 셈.
+    let mut folder_files = vec![];
+    let (mut images_total, mut bytes_total) = (0, 0);
+    for folder_path in &folder_list {
+        let files: Vec<PathBuf> = folder_path
+            .read_dir()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            //만들다 만 zip(.zip.part)과 cbz, 지난번에 남은 임시 파일, .jxlzip.toml 도 건너뜀.
+            .filter(|path| {
+                let ext = path.extension().unwrap_or_default();
+                path.is_file()
+                    && ext != "zip"
+                    && ext != "cbz"
+                    && ext != "part"
+                    && !is_temp_file(path)
+                    && !config::is_folder_config(path)
+            })
+            .collect();
+        for path in &files {
+            let ext = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+            if SourceFormat::from_extension(ext).is_some()
+                && skip_reason(work_folder, args_of(folder_path), path).is_none()
+            {
+                images_total += 1;
+                bytes_total += path.metadata().map(|m| m.len()).unwrap_or_default();
+            }
+        }
+        folder_files.push(files);
+    }
+    let progress = Progress::new(images_total, folder_list.len(), bytes_total);
+
+    let mut failed_folders = 0;
+    let mut archive_groups: HashMap<PathBuf, ArchiveGroup> = HashMap::new();
+
+    //모든 폴더의 파일을 한 풀에서 변환하고, 폴더의 마지막 파일이 끝나면 zip 을 만듦.
+    //폴더를 지우거나 하위 폴더를 묶을 때는 하위 폴더부터 끝냄.
+    let result = scheduler::run(
+        &folder_list,
+        folder_files,
+        cjxl_args.zip_depth.is_some() || folder_args.values().any(|args| args.delete_folder_plag),
+        |path| {
+            let file_args = args_of(path.parent().unwrap_or(work_folder));
+            //필터에 걸린 원본은 열어보지도 않음.
+            if let Some(reason) = skip_reason(work_folder, file_args, path) {
+                let input_bytes = path.metadata().map(|m| m.len()).unwrap_or_default();
+                report.skipped(path, &reason, input_bytes);
+                return Ok(JXL::Skipped(path.clone(), reason));
+            }
+            match is_image_file(path) {
+                Ok(image_format) => {
+                    //변환 뒤에는 원본이 지워졌을 수 있음.
+                    let input_bytes = path.metadata().map(|m| m.len()).unwrap_or_default();
+                    let result = convert_image(
+                        encoder,
+                        decoder,
+                        file_args,
+                        &worklist,
+                        report,
+                        path,
+                        image_format,
+                    )
+                    .inspect_err(|err| report.failed(path, err));
+                    let output_bytes = match &result {
+                        Ok(JXL::Converted { jxl_path, .. }) => {
+                            Some(jxl_path.metadata().map(|m| m.len()).unwrap_or_default())
+                        }
+                        Ok(_) => Some(input_bytes),
+                        Err(_) => None,
+                    };
+                    progress.file_done(input_bytes, output_bytes);
+                    result
+                }
+                Err(err) => {
+                    let result = if err.contains("This file is skip") {
+                        Ok(JXL::ExistFromBegin(path.clone()))
+                    } else {
+                        Err(err)
+                    };
+                    //합계에 넣은 원본이면 그대로 넣는 파일과 실패한 파일도 셈.
+                    if path
+                        .extension()
+                        .and_then(OsStr::to_str)
+                        .and_then(SourceFormat::from_extension)
+                        .is_some()
+                    {
+                        let input_bytes = path.metadata().map(|m| m.len()).unwrap_or_default();
+                        let output_bytes = result.is_ok().then_some(input_bytes);
+                        progress.file_done(input_bytes, output_bytes);
                     }
-                } else if let Some(args_str) = arg.strip_prefix("png_args=") {
-                    jxlargs.png_args = args_str
-                        .trim_matches(args_pattern)
-                        .split(',')
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                } else if let Some(args_str) = arg.strip_prefix("jpg_args=") {
-                    jxlargs.jpg_args = args_str
-                        .trim_matches(args_pattern)
-                        .split(',')
-                        .map(|s| s.trim().to_string())
-                        .collect();
+                    result
+                }
+            }
+        },
+        |folder, mut pack_files_list| {
+            let folder_path = &folder_list[folder];
+            //건너뛴 하위 폴더가 남으므로 폴더를 지우지 않게 함.
+            if let Some(skipped) = skipped_folder(folder_path, &scanned) {
+                pack_files_list.push(Ok(JXL::Skipped(skipped, "folder is skipped".to_string())));
+            }
+            if finish_folder(
+                work_folder,
+                args_of(folder_path),
+                &worklist,
+                report,
+                &mut archive_groups,
+                folder_path,
+                pack_files_list,
+            )? {
+                failed_folders += 1;
+            }
+            progress.folder_done();
+            Ok(())
+        },
+    );
+    progress.finish();
+    result?;
+
+    //archive_root 가 이미 끝난 폴더라서 묶지 못한 하위 폴더. Ctrl-C 로 멈췄으면 다음에 이어서 묶음.
+    for (archive_root, group) in archive_groups
+        .into_iter()
+        .filter(|_| !interrupt::interrupted())
+    {
+        warn!(
+            "{} folders are left without zip because already done: {}",
+            group.folders.len(),
+            archive_root.display()
+        );
+    }
+
+    //작업 리스트 저장.
+    worklist.save()?;
+    if interrupt::interrupted() {
+        info!("Stopped: {}", work_folder.display());
+        return Ok(failed_folders);
+    }
+
+    info!("All done: {}", work_folder.display());
+    let duration_time = start.elapsed();
+    let (hours, minutes, seconds, milliseconds) = time_display(duration_time);
+    info!(
+        "Duration time:{:02}:{:02}:{:02}.{:03}",
+        hours, minutes, seconds, milliseconds
+    );
+    info!(
+        "File search time:{:02}:{:02}:{:02}.{:03}",
+        fs_hours, fs_minutes, fs_seconds, fs_milliseconds
+    );
+
+    Ok(failed_folders)
+}
+
+//폴더의 마지막 파일이 끝나면 zip 을 만들고 폴더를 지움. 폴더가 실패하면 true.
+fn finish_folder(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    worklist: &Worklist,
+    report: &Report,
+    archive_groups: &mut HashMap<PathBuf, ArchiveGroup>,
+    folder_path: &Path,
+    pack_files_list: Vec<Result<JXL, String>>,
+) -> Result<bool, Box<dyn Error>> {
+    let zip_options = FileOptions::default()
+        .compression_method(Stored)
+        .unix_permissions(0o755);
+
+    info!("folder_path: {}", folder_path.display());
+
+    let mut delete_folder_plag = cjxl_args.delete_folder_plag;
+    let mut can_i_make_zip_file = cjxl_args.make_zip_plag;
+
+    //이번 설정으로 변환한 이미지가 있는지. 없으면 설정이 바뀌어도 다시 할 일이 없음.
+    let converted = pack_files_list.iter().any(|pack_file| {
+        matches!(
+            pack_file,
+            Ok(JXL::Converted { .. }) | Ok(JXL::SourceKept(_))
+        )
+    });
+    let setting_id = converted.then(|| worklist.setting_id(cjxl_args));
+    let mut failed = false;
+    //다시 변환하는 폴더면 지난번에 만든 zip 을 바꿈. 사용자가 둔 같은 이름의 zip 은 그대로 둠.
+    let replace = worklist.reencoded_archive(folder_path);
+
+    //zip_depth 가 있으면 하위 폴더의 jxl 을 모아뒀다가 archive_root 에서 한 번에 zip 으로 묶음.
+    let archive_root = cjxl_args
+        .zip_depth
+        .filter(|_| cjxl_args.make_zip_plag)
+        .and_then(|zip_depth| archive_root(work_folder, folder_path, zip_depth));
+    if let Some(archive_root) = archive_root {
+        let group = archive_groups.entry(archive_root.clone()).or_default();
+        group.folders.push((folder_path.to_path_buf(), setting_id));
+        for pack_file in pack_files_list {
+            match pack_file {
+                Ok(JXL::Converted {
+                    image_path,
+                    jxl_path,
+                }) => {
+                    group.files.push(jxl_path);
+                    group.sources.push(image_path);
+                }
+                Ok(JXL::SourceKept(image_path)) => {
+                    group.files.push(image_path.clone());
+                    group.source_files.push(image_path.clone());
+                    group.sources.push(image_path);
+                }
+                Ok(JXL::ExistFromBegin(jxl_path)) => group.files.push(jxl_path),
+                Ok(JXL::Skipped(path, reason)) => {
+                    info!("Skipped: {} ({})", path.display(), reason);
+                    group.skipped = true;
+                }
+                Err(err) => {
+                    warn!("{}\npass this folder", err);
+                    group.failed = true;
+                }
+            }
+        }
+        if folder_path != archive_root {
+            worklist.sync()?;
+            return Ok(false);
+        }
+
+        let mut group = archive_groups.remove(&archive_root).unwrap_or_default();
+        group.files.sort();
+        if group.failed {
+            //하위 폴더 하나라도 실패하면 zip 을 만들지 않음.
+            info!("Do not make zip file");
+            failed = true;
+        } else if group.files.is_empty() {
+            info!("No image file in folder");
+        } else {
+            let made_zip = match make_zip(
+                folder_path,
+                zip_options,
+                group.files,
+                &group.source_files,
+                cjxl_args.archive_kind(folder_path),
+                replace.as_deref(),
+            ) {
+                Ok(made_zip) => made_zip,
+                Err(err) => {
+                    warn!(
+                        "Failed to make zip: {}\nerror message: {}",
+                        folder_path.display(),
+                        err
+                    );
+                    return Ok(true);
                 }
+            };
+            for image_path in &group.sources {
+                worklist.set_status(image_path, FileStatus::Packed)?;
+            }
+            if let Some(made_zip) = &made_zip {
+                worklist.set_archive(folder_path, &made_zip.path)?;
+                report.packed(&group.sources, &made_zip.path);
+                delete_packed_sources(&made_zip.source_files, cjxl_args, report)?;
+            }
+            if cjxl_args.delete_folder_plag && !group.skipped {
+                delete_path(folder_path, cjxl_args)?;
             }
         }
-        Err(_) => {
-            return Err("Failed to open file".to_string());
+        for (folder, setting_id) in &group.folders {
+            worklist.folder_done(folder, *setting_id)?;
+        }
+        worklist.sync()?;
+        return Ok(failed);
+    }
+
+    if pack_files_list.is_empty() {
+        info!("No image file in folder");
+        worklist.folder_done(folder_path, None)?;
+        return Ok(false);
+    }
+
+    //error check
+    for pack_file in &pack_files_list {
+        if let Err(err) = pack_file {
+            warn!("{}\npass this folder", err);
+            can_i_make_zip_file = false;
+            delete_folder_plag = false;
+            failed = true;
+            break;
         }
     }
-    Ok(jxlargs)
+
+    //건너뛴 원본과 폴더는 zip 에 들어가지 않으므로 폴더와 함께 지우면 안 됨.
+    if pack_files_list
+        .iter()
+        .any(|pack_file| matches!(pack_file, Ok(JXL::Skipped(..))))
+    {
+        delete_folder_plag = false;
+    }
+
+    if !can_i_make_zip_file {
+        //파일 하나라도 이미지 변환에 실패하는 경우, zip 파일을 만들지 않음.
+        //또는 사용자가 zip 파일을 만들지 않겠다고 설정한 경우.
+        info!("Do not make zip file");
+        worklist.folder_done(folder_path, setting_id)?;
+        worklist.sync()?;
+        return Ok(failed);
+    }
+
+    //zip 파일 만들기 - png, jpg를 포함한 모든 이미지 파일을 zip으로 묶음.
+    let mut packed_sources = vec![];
+    //zip 에 넣은 뒤에도 지우지 않을 원본. delete_source_image 가 꺼져 있을 때.
+    let mut source_files = vec![];
+    let pack_files_list: Vec<_> = pack_files_list
+        .into_iter()
+        .filter_map({
+            |pack_file| match pack_file {
+                Ok(JXL::Converted {
+                    image_path,
+                    jxl_path,
+                }) => {
+                    packed_sources.push(image_path);
+                    Some(jxl_path)
+                }
+                Ok(JXL::SourceKept(image_path)) => {
+                    source_files.push(image_path.clone());
+                    packed_sources.push(image_path.clone());
+                    Some(image_path)
+                }
+                Ok(JXL::ExistFromBegin(jxl_path)) => Some(jxl_path),
+                Ok(JXL::Skipped(path, reason)) => {
+                    info!("Skipped: {} ({})", path.display(), reason);
+                    None
+                }
+                Err(err) => {
+                    //not use this line, but still keep it.
+                    warn!("{}\npass this folder", err);
+                    None
+                }
+            }
+        })
+        .collect();
+    if pack_files_list.is_empty() {
+        info!("No image file in folder");
+        worklist.folder_done(folder_path, setting_id)?;
+        worklist.sync()?;
+        return Ok(false);
+    }
+    let made_zip = match make_zip(
+        folder_path,
+        zip_options,
+        pack_files_list,
+        &source_files,
+        cjxl_args.archive_kind(folder_path),
+        replace.as_deref(),
+    ) {
+        Ok(made_zip) => made_zip,
+        Err(err) => {
+            //zip 파일 생성에 실패하면 폴더를 지우지 않고, 다음 실행때 다시 시도함.
+            warn!(
+                "Failed to make zip: {}\nerror message: {}",
+                folder_path.display(),
+                err
+            );
+            worklist.sync()?;
+            return Ok(true);
+        }
+    };
+    for image_path in &packed_sources {
+        worklist.set_status(image_path, FileStatus::Packed)?;
+    }
+    if let Some(made_zip) = &made_zip {
+        worklist.set_archive(folder_path, &made_zip.path)?;
+        report.packed(&packed_sources, &made_zip.path);
+        delete_packed_sources(&made_zip.source_files, cjxl_args, report)?;
+    }
+
+    if delete_folder_plag {
+        //폴더 삭제. 만약 삭제하려는 폴더 안에 다른 폴더, 이미지가 아닌 파일이 있으면 폴더를 삭제하지 않음.
+        delete_path(folder_path, cjxl_args)?;
+    }
+
+    //work_info.worklist[&folder_path] = true;//why rust not allow IndexMut????
+    //work_info 업데이트
+    worklist.folder_done(folder_path, setting_id)?;
+    worklist.sync()?;
+    Ok(false)
+}
+
+//이미지 하나를 jxl 로 변환, 확인, 원본 삭제까지. 단계마다 작업 내역에 남김.
+//지난번에 변환을 마친 파일은 원본 해시가 같고 jxl 이 남아있으면 다시 변환하지 않음.
+fn convert_image(
+    encoder: &dyn Encoder,
+    decoder: Option<&dyn Decoder>,
+    cjxl_args: &JxlArgs,
+    worklist: &Worklist,
+    report: &Report,
+    image_path: &PathBuf,
+    image_format: image::ImageFormat,
+) -> Result<JXL, String> {
+    let jxl_path = image_path.with_extension("jxl");
+    let source_size = fs::metadata(image_path)
+        .map_err(|err| err.to_string())?
+        .len();
+    if let Some(format) = SourceFormat::from_image_format(image_format) {
+        report.start(
+            image_path,
+            format.config_key(),
+            cjxl_args
+                .args_for(format)
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            source_size,
+        );
+    }
+    let source_kept = || {
+        report.update(image_path, |row| {
+            row.outcome = Some(Outcome::SourceKept);
+            row.output_bytes = Some(source_size);
+        })
+    };
+    let source_hash = finalize_xxhash(image_path)?;
+    let setting_id = worklist.setting_id(cjxl_args);
+
+    let recorded = worklist.file(image_path);
+    //지난번에 원본을 남기기로 한 파일.
+    if recorded
+        .as_ref()
+        .is_some_and(|state| state.is_source_kept(source_hash, setting_id))
+    {
+        info!("Already kept source: {}", image_path.display());
+        source_kept();
+        return Ok(JXL::SourceKept(image_path.clone()));
+    }
+    let resumed = recorded
+        .as_ref()
+        .filter(|state| state.is_converted(source_hash, setting_id, &jxl_path));
+    let mut status = match resumed {
+        Some(state) => {
+            info!("Already converted: {}", jxl_path.display());
+            report.update(image_path, |row| {
+                row.outcome = Some(Outcome::Resumed);
+                if state.status >= FileStatus::Verified {
+                    row.verification = Some(Verification::Passed);
+                }
+            });
+            state.status
+        }
+        None => {
+            if let Some(state) = &recorded {
+                //이 프로그램이 쓰다 만 jxl 이나, 바뀌기 전 원본 또는 설정으로 만든 jxl. 지우고 다시 변환함.
+                if state.jxl_path.is_file() {
+                    info!(
+                        "Remove unfinished or stale jxl: {}",
+                        state.jxl_path.display()
+                    );
+                    fs::remove_file(&state.jxl_path).map_err(|err| err.to_string())?;
+                }
+                for temp_path in temp_files(&state.jxl_path) {
+                    let _ = fs::remove_file(temp_path);
+                }
+            }
+            if cjxl_args.size_policy == SizePolicy::Source {
+                //변환하지 않고 원본을 그대로 zip 에 넣음.
+                worklist.set_file(
+                    image_path,
+                    FileState {
+                        status: FileStatus::SourceKept,
+                        source_hash,
+                        jxl_path: jxl_path.clone(),
+                        output_size: 0,
+                        setting_id,
+                    },
+                )?;
+                source_kept();
+                return Ok(JXL::SourceKept(image_path.clone()));
+            }
+            worklist.set_file(
+                image_path,
+                FileState {
+                    status: FileStatus::Pending,
+                    source_hash,
+                    jxl_path: jxl_path.clone(),
+                    output_size: 0,
+                    setting_id,
+                },
+            )?;
+            let encode_start = Instant::now();
+            image_to_jxl(encoder, cjxl_args, image_path, &jxl_path, &image_format)?;
+            let encode_ms = encode_start.elapsed().as_millis() as u64;
+            if let Err(err) = metadata::copy_file_times(image_path, &jxl_path) {
+                warn!(
+                    "Failed to copy file times: {}\nerror message: {}",
+                    jxl_path.display(),
+                    err
+                );
+            }
+            let metadata_problems = check_metadata(cjxl_args, image_path, &jxl_path, image_format);
+            report.update(image_path, |row| {
+                row.outcome = Some(Outcome::Converted);
+                row.encode_ms = Some(encode_ms);
+                if !metadata_problems.is_empty() {
+                    row.metadata = Some(metadata_problems.join(", "));
+                }
+            });
+            let output_size = fs::metadata(&jxl_path)
+                .map_err(|err| err.to_string())?
+                .len();
+            worklist.set_file(
+                image_path,
+                FileState {
+                    status: FileStatus::Converted,
+                    source_hash,
+                    jxl_path: jxl_path.clone(),
+                    output_size,
+                    setting_id,
+                },
+            )?;
+            FileStatus::Converted
+        }
+    };
+
+    //jxl 이 충분히 작지 않으면 jxl 을 지우고 원본을 zip 에 넣음.
+    let jxl_size = fs::metadata(&jxl_path)
+        .map_err(|err| err.to_string())?
+        .len();
+    if !cjxl_args
+        .size_policy
+        .keep_jxl(source_size, jxl_size, cjxl_args.min_savings)
+    {
+        info!(
+            "JXL is not small enough so keep source: {} ({} -> {} bytes)",
+            image_path.display(),
+            source_size,
+            jxl_size
+        );
+        fs::remove_file(&jxl_path).map_err(|err| err.to_string())?;
+        worklist.set_status(image_path, FileStatus::SourceKept)?;
+        source_kept();
+        return Ok(JXL::SourceKept(image_path.clone()));
+    }
+
+    if let Some(decoder) = decoder.filter(|_| status < FileStatus::Verified) {
+        match decoder::verify_jxl(decoder, cjxl_args, image_path, &jxl_path, image_format) {
+            Ok(verified) => debug!("{:?}: {}", verified, jxl_path.display()),
+            Err(err) => {
+                //원본과 다른 jxl 은 지우고, 원본은 남겨둠.
+                let _ = fs::remove_file(&jxl_path);
+                worklist.set_status(image_path, FileStatus::Pending)?;
+                report.update(image_path, |row| {
+                    row.verification = Some(Verification::Failed);
+                });
+                return Err(format!(
+                    "Failed file: {} \nerror message: Verification failed: {}",
+                    image_path.display(),
+                    err
+                ));
+            }
+        }
+        status = FileStatus::Verified;
+        worklist.set_status(image_path, status)?;
+        report.update(image_path, |row| {
+            row.verification = Some(Verification::Passed);
+        });
+    }
+
+    if cjxl_args.delete_source_image_plag && status < FileStatus::SourceDeleted {
+        //원본 파일 삭제. 실패하면 이 파일만 실패로 남기고, 다음 실행에서 다시 지움.
+        delete_path(image_path, cjxl_args).map_err(|err| {
+            format!(
+                "Failed to delete source: {}\nerror message: {}",
+                image_path.display(),
+                err
+            )
+        })?;
+        worklist.set_status(image_path, FileStatus::SourceDeleted)?;
+    }
+    report.update(image_path, |row| {
+        row.output_bytes = Some(jxl_size);
+        if cjxl_args.delete_source_image_plag {
+            row.deletion = Some(cjxl_args.deletion());
+        }
+    });
+    Ok(JXL::Converted {
+        image_path: image_path.clone(),
+        jxl_path,
+    })
+}
+
+//원본의 Exif, XMP, ICC 가 jxl 에 남았는지, strip 한 것은 빠졌는지 확인하고 문제를 경고함.
+fn check_metadata(
+    cjxl_args: &JxlArgs,
+    image_path: &Path,
+    jxl_path: &Path,
+    image_format: image::ImageFormat,
+) -> Vec<String> {
+    let Some(format) = SourceFormat::from_image_format(image_format) else {
+        return vec![];
+    };
+    let (Ok(source), Ok(jxl)) = (fs::read(image_path), fs::read(jxl_path)) else {
+        return vec![];
+    };
+    let problems = metadata::check_metadata(&source, format, &jxl, cjxl_args.strip_for(format));
+    if !problems.is_empty() {
+        warn!(
+            "Metadata of {}: {}",
+            image_path.display(),
+            problems.join(", ")
+        );
+    }
+    problems
+}
+
+//이미 변환된 jxl 파일만 zip으로 묶음. cjxl은 실행하지 않음.
+fn zip_work_folder(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+) -> Result<usize, Box<dyn Error>> {
+    let zip_options = FileOptions::default()
+        .compression_method(Stored)
+        .unix_permissions(0o755);
+
+    let mut failed_folders = 0;
+    let folder_list = scan_folders(work_folder, cjxl_args, folder_config)?;
+    //건너뛴 폴더의 jxl 은 zip_depth 로 묶을 때도 넣지 않음.
+    let scanned: HashSet<PathBuf> = folder_list.iter().map(|(path, _)| path.clone()).collect();
+    for (folder_path, folder_args) in folder_list {
+        if interrupt::interrupted() {
+            break;
+        }
+        let cjxl_args: &JxlArgs = &folder_args;
+        //zip_depth 보다 깊은 폴더는 archive_root 의 zip 에 함께 들어감.
+        let files: Vec<PathBuf> = match cjxl_args
+            .zip_depth
+            .and_then(|zip_depth| archive_root(work_folder, &folder_path, zip_depth))
+        {
+            Some(archive_root) if archive_root != folder_path => continue,
+            Some(_) => WalkDir::new(&folder_path)
+                .sort(true)
+                .into_iter()
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+            None => folder_path
+                .read_dir()?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+        };
+
+        let mut jxl_files = vec![];
+        let mut image_left = false;
+        for path in files {
+            if !path.is_file() || is_temp_file(&path) {
+                continue;
+            }
+            if !path.parent().is_some_and(|parent| scanned.contains(parent)) {
+                continue;
+            }
+            let ext = path
+                .extension()
+                .and_then(OsStr::to_str)
+                .unwrap_or_default()
+                .to_lowercase();
+            if ext == "jxl" {
+                jxl_files.push(path);
+            } else if SourceFormat::from_extension(&ext).is_some() {
+                image_left = true;
+            }
+        }
+
+        if jxl_files.is_empty() {
+            continue;
+        }
+        info!("folder_path: {}", folder_path.display());
+        if let Err(err) = make_zip(
+            &folder_path,
+            zip_options,
+            jxl_files,
+            &[],
+            cjxl_args.archive_kind(&folder_path),
+            None,
+        ) {
+            warn!(
+                "Failed to make zip: {}\nerror message: {}",
+                folder_path.display(),
+                err
+            );
+            failed_folders += 1;
+            continue;
+        }
+
+        //변환되지 않은 이미지나 건너뛴 폴더가 남아있으면 폴더를 지우지 않음.
+        if cjxl_args.delete_folder_plag {
+            if image_left || skipped_folder(&folder_path, &scanned).is_some() {
+                warn!(
+                    "Not converted image or skipped folder left so do not delete folder: {}",
+                    folder_path.display()
+                );
+            } else {
+                delete_path(&folder_path, cjxl_args)?;
+            }
+        }
+    }
+    Ok(failed_folders)
+}
+
+fn verify_work_folder(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+) -> Result<usize, Box<dyn Error>> {
+    let folder_list = scan_folders(work_folder, cjxl_args, folder_config)?;
+    let zip_files = find_zip_files(work_folder, &folder_list);
+    let mut failed_zips = 0;
+    for (zip_path, _) in &zip_files {
+        if interrupt::interrupted() {
+            break;
+        }
+        match check_zip(zip_path) {
+            Ok((count, xxh3_checked)) => info!(
+                "Verified {} files ({} with xxh3): {}",
+                count,
+                xxh3_checked,
+                zip_path.display()
+            ),
+            Err(err) => {
+                warn!("Broken zip: {}\nerror message: {}", zip_path.display(), err);
+                failed_zips += 1;
+            }
+        }
+    }
+    info!(
+        "{}: {} zip checked, {} broken",
+        work_folder.display(),
+        zip_files.len(),
+        failed_zips
+    );
+    Ok(failed_zips)
+}
+
+//zip 을 폴더로 풀고, 디코더가 있으면 jxl 을 원래 포맷으로 되돌림. 실패한 zip 과 jxl 수를 돌려줌.
+fn unpack_work_folder(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+    decoder: Option<&dyn Decoder>,
+) -> Result<usize, Box<dyn Error>> {
+    let folder_list = scan_folders(work_folder, cjxl_args, folder_config)?;
+    let mut failed_zips = 0;
+    let mut jxl_files = vec![];
+    for (zip_path, _) in find_zip_files(work_folder, &folder_list) {
+        if interrupt::interrupted() {
+            break;
+        }
+        let folder_path = zip_path.with_extension("");
+        if folder_path.exists() {
+            warn!("Folder already exists so skip: {}", folder_path.display());
+            continue;
+        }
+        let result = File::open(&zip_path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|file| Ok(ZipArchive::new(file)?.extract(&folder_path)?));
+        match result {
+            Ok(_) => {
+                info!("Unpacked: {}", zip_path.display());
+                //zip_depth 로 묶은 zip 은 하위 폴더가 있음.
+                jxl_files.extend(
+                    WalkDir::new(&folder_path)
+                        .into_iter()
+                        .filter_map(Result::ok)
+                        .map(|entry| entry.path())
+                        .filter(|path| {
+                            path.is_file() && path.extension().is_some_and(|ext| ext == "jxl")
+                        }),
+                );
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to unpack: {}\nerror message: {}",
+                    zip_path.display(),
+                    err
+                );
+                failed_zips += 1;
+            }
+        }
+    }
+
+    let Some(decoder) = decoder else {
+        return Ok(failed_zips);
+    };
+    let failed_files: usize = jxl_files
+        .par_iter()
+        .filter(|_| !interrupt::interrupted())
+        .map(|jxl_path| match decoder::restore_jxl(decoder, jxl_path) {
+            Ok(output_path) => {
+                debug!("Restored: {}", output_path.display());
+                0
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to restore: {}\nerror message: {}",
+                    jxl_path.display(),
+                    err
+                );
+                1
+            }
+        })
+        .sum();
+    info!(
+        "{}: {} jxl restored, {} failed",
+        work_folder.display(),
+        jxl_files.len() - failed_files,
+        failed_files
+    );
+    Ok(failed_zips + failed_files)
+}
+
+fn show_status(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+) -> Result<usize, Box<dyn Error>> {
+    let work_info = match WorkInfo::load(work_folder) {
+        Some(work_info) => work_info,
+        None => {
+            info!("No worklist: {}", work_folder.display());
+            return Ok(0);
+        }
+    };
+
+    let done = work_info.worklist.values().filter(|&&done| done).count();
+    info!(
+        "{}: {} / {} folders done\ncjxl version: {}",
+        work_info.work_folder_path,
+        done,
+        work_info.worklist.len(),
+        work_info.encoder_version
+    );
+    //지금 설정과 비교. 인코더를 실행하지 않으므로 마지막으로 쓴 cjxl 버전을 씀.
+    //지워진 폴더는 작업 폴더의 설정과 비교함.
+    let setting_ids: HashMap<PathBuf, u64> = scan_folders(work_folder, cjxl_args, folder_config)?
+        .into_iter()
+        .map(|(path, folder_args)| {
+            let setting_id = EncodeSetting::new(&folder_args, &work_info.encoder_version).id();
+            (path, setting_id)
+        })
+        .collect();
+    let work_setting_id = EncodeSetting::new(cjxl_args, &work_info.encoder_version).id();
+    let setting_id_of = |path: &Path| setting_ids.get(path).copied().unwrap_or(work_setting_id);
+    work_info.report_settings(&setting_ids.values().copied().collect());
+    for path in work_info.stale_folders(setting_id_of) {
+        info!("stale: {}", path.display());
+    }
+    //마지막으로 변환할 때 .jxlzip.toml 로 설정이 달랐던 폴더.
+    if !work_info.folder_args.is_empty() {
+        info!(
+            "{} folders have their own settings",
+            work_info.folder_args.len()
+        );
+        let mut paths: Vec<_> = work_info.folder_args.keys().collect();
+        paths.sort();
+        for path in paths {
+            debug!("    {}", path.display());
+        }
+    }
+    //파일별 상태 개수.
+    let mut status_count: BTreeMap<FileStatus, usize> = BTreeMap::new();
+    let mut output_size = 0;
+    for state in work_info.files.values() {
+        *status_count.entry(state.status).or_default() += 1;
+        output_size += state.output_size;
+    }
+    for (status, count) in &status_count {
+        info!("{:?}: {} files", status, count);
+    }
+    if !work_info.files.is_empty() {
+        info!("jxl size: {} bytes", output_size);
+    }
+    let mut pending: Vec<_> = work_info
+        .worklist
+        .iter()
+        .filter(|(_, &done)| !done)
+        .map(|(path, _)| path)
+        .collect();
+    pending.sort();
+    for path in pending {
+        info!("pending: {}", path.display());
+    }
+    Ok(0)
+}
+
+//설정 파일과 profile 을 적용하고, --config 파일과 명령줄 옵션이 그보다 우선함.
+//작업 폴더에 .jxlzip.toml 이 없을 때의 설정.
+fn resolve_jxl_args(config: &Config, folder_config: &FolderConfig) -> Result<JxlArgs, String> {
+    let cjxl_args = folder_config.global_args();
+    if cjxl_args.args_for(SourceFormat::Png).is_none()
+        || cjxl_args.args_for(SourceFormat::Jpeg).is_none()
+    {
+        return Err(format!(
+            "args.png and args.jpg must be set, read: {}",
+            config.sources().join(", ")
+        ));
+    }
+    cjxl_args
+        .check_strip()
+        .map_err(|err| format!("{}, read: {}", err, config.sources().join(", ")))?;
+    Ok(cjxl_args)
+}
+
+fn log_cjxl_args(cjxl_args: &JxlArgs) {
+    let mut cjxl_args_text = format!(
+        r#"cjxl_args:
+            delete_folder={}
+            delete_source_image={}
+            make_zip={}
+            dont_use_trashcan_just_delete={}
+            verify_image={}
+            zip_depth={:?}
+            size_policy={:?}
+            min_savings={}%
+            comic={}
+            comic_folders={:?}
+            max_encodes={:?}
+            memory_budget={:?}MB
+            low_priority={}
+            min_bytes={}
+            max_pixels={}
+            min_age_days={}"#,
+        cjxl_args.delete_folder_plag,
+        cjxl_args.delete_source_image_plag,
+        cjxl_args.make_zip_plag,
+        cjxl_args.dont_use_trashcan_just_delete,
+        cjxl_args.verify_image,
+        cjxl_args.zip_depth,
+        cjxl_args.size_policy,
+        cjxl_args.min_savings,
+        cjxl_args.comic,
+        cjxl_args.comic_folders,
+        cjxl_args.max_encodes,
+        cjxl_args.memory_budget,
+        cjxl_args.low_priority,
+        cjxl_args.min_bytes,
+        cjxl_args.max_pixels,
+        cjxl_args.min_age_days,
+    );
+    for (format, args) in &cjxl_args.format_args {
+        cjxl_args_text.push_str(&format!(
+            "\n            {}_args={:?}",
+            format.config_key(),
+            args
+        ));
+    }
+    for (format, kinds) in &cjxl_args.format_strip {
+        cjxl_args_text.push_str(&format!(
+            "\n            {}_strip={:?}",
+            format.config_key(),
+            kinds
+        ));
+    }
+    let filter = &cjxl_args.filter;
+    for (key, patterns) in [
+        ("include_folders", &filter.include_folders),
+        ("exclude_folders", &filter.exclude_folders),
+        ("include_files", &filter.include_files),
+        ("exclude_files", &filter.exclude_files),
+    ] {
+        if !patterns.is_empty() {
+            cjxl_args_text.push_str(&format!("\n            {}={:?}", key, patterns));
+        }
+    }
+    info!("{}", cjxl_args_text);
 }
 
 fn time_display(duration_time: std::time::Duration) -> (u128, u128, u128, u128) {
@@ -601,14 +1873,56 @@ fn time_display(duration_time: std::time::Duration) -> (u128, u128, u128, u128)
     (hours, minutes, seconds, milliseconds)
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 struct JxlArgs {
     delete_folder_plag: bool,
     delete_source_image_plag: bool,
     make_zip_plag: bool,
     dont_use_trashcan_just_delete: bool,
-    png_args: Vec<String>,
-    jpg_args: Vec<String>,
+    //원본을 지우기 전에 jxl 을 디코딩해서 원본과 비교.
+    verify_image: bool,
+    //포맷별 cjxl 인자. png_args, jpg_args 를 일반화한 것.
+    format_args: BTreeMap<SourceFormat, Vec<String>>,
+    //포맷별로 jxl 에서 뺄 메타데이터. 없으면 모두 남김.
+    format_strip: BTreeMap<SourceFormat, Vec<MetadataKind>>,
+    //zip 을 만들 폴더 깊이. 작업 폴더가 0. 더 깊은 폴더는 상대 경로로 같은 zip 에 들어감.
+    //None 이면 폴더마다 zip 을 만듦.
+    zip_depth: Option<usize>,
+    //jxl 과 원본 중 무엇을 남길지.
+    size_policy: SizePolicy,
+    //size_policy 가 smaller 일 때, jxl 이 원본보다 이만큼(%) 이상 작아야 jxl 을 남김.
+    min_savings: f64,
+    //모든 폴더를 cbz 로 만듦.
+    comic: bool,
+    //이름이 이 패턴에 맞는 폴더만 cbz 로 만듦. * 와 ? 를 쓸 수 있음.
+    comic_folders: Vec<String>,
+    //인코더 위치는 작업 결과에 영향이 없으므로 작업 내역에 저장하지 않음.
+    #[serde(skip)]
+    cjxl_path: Option<PathBuf>,
+    //사용한 인코더는 encoder_version 으로 남음.
+    #[serde(skip)]
+    encoder: EncoderKind,
+    //동시에 돌리는 인코더 수. None 이면 코어 수만큼.
+    #[serde(skip)]
+    max_encodes: Option<usize>,
+    //동시에 도는 인코더가 쓸 메모리 어림값의 합(MB). 큰 이미지는 줄을 섬.
+    #[serde(skip)]
+    memory_budget: Option<u64>,
+    //프로세스와 cjxl 의 우선순위를 낮춤.
+    #[serde(skip)]
+    low_priority: bool,
+    //변환할 폴더와 이미지를 고르는 패턴. 고르지 않은 이미지는 작업 결과가 아님.
+    #[serde(skip)]
+    filter: PathFilter,
+    //이보다 작은 원본은 건너뜀. 0 이면 모두 변환.
+    #[serde(skip)]
+    min_bytes: u64,
+    //가로x세로가 이보다 큰 원본은 건너뜀. 0 이면 제한 없음.
+    #[serde(skip)]
+    max_pixels: u64,
+    //최근 이 날 수 안에 수정한 원본은 건너뜀. 0 이면 모두 변환.
+    #[serde(skip)]
+    min_age_days: u64,
 }
 impl Default for JxlArgs {
     fn default() -> Self {
@@ -617,37 +1931,183 @@ impl Default for JxlArgs {
             delete_source_image_plag: false,
             make_zip_plag: true,
             dont_use_trashcan_just_delete: false,
-            png_args: vec![],
-            jpg_args: vec![],
+            verify_image: false,
+            format_args: BTreeMap::new(),
+            format_strip: BTreeMap::new(),
+            zip_depth: None,
+            size_policy: SizePolicy::Jxl,
+            min_savings: 0.0,
+            comic: false,
+            comic_folders: vec![],
+            cjxl_path: None,
+            encoder: EncoderKind::Cjxl,
+            max_encodes: None,
+            memory_budget: None,
+            low_priority: false,
+            filter: PathFilter::default(),
+            min_bytes: 0,
+            max_pixels: 0,
+            min_age_days: 0,
         }
     }
 }
+impl JxlArgs {
+    //원본을 지울 때 쓰는 방법. report 에 남김.
+    fn deletion(&self) -> Deletion {
+        if self.dont_use_trashcan_just_delete {
+            Deletion::Deleted
+        } else {
+            Deletion::Trashed
+        }
+    }
 
-enum JXL {
-    ExistFromBegin(PathBuf),
-    Converted(PathBuf),
-}
+    //자기 인자가 없는 포맷은 png_args 를 씀. jpg 는 lossless_jpeg 때문에 따로 써야 함.
+    fn args_for(&self, format: SourceFormat) -> Option<&Vec<String>> {
+        self.format_args
+            .get(&format)
+            .filter(|args| !args.is_empty())
+            .or_else(|| match format {
+                SourceFormat::Jpeg => None,
+                _ => self
+                    .format_args
+                    .get(&SourceFormat::Png)
+                    .filter(|args| !args.is_empty()),
+            })
+    }
 
-#[derive(Serialize, Deserialize)]
-struct WorkInfo {
-    work_folder_path: String,
-    work_setting: JxlArgs,
-    worklist: XxHashMap<PathBuf, bool>,
-}
-impl WorkInfo {
-    fn new(work_folder_path: String, work_setting: JxlArgs) -> Self {
-        WorkInfo {
-            work_folder_path,
-            work_setting,
-            worklist: XxHashMap::default(),
+    fn strip_for(&self, format: SourceFormat) -> &[MetadataKind] {
+        self.format_strip
+            .get(&format)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    //icc 는 png 를 거쳐서만 뺄 수 있음. 그러면 잃는 것이 있는 설정은 에러.
+    fn check_strip(&self) -> Result<(), String> {
+        for (&format, strip) in &self.format_strip {
+            if !strip.contains(&MetadataKind::Icc) {
+                continue;
+            }
+            let key = format.config_key();
+            //픽셀로 디코딩하면 jpg 로 되돌릴 수 있는 무손실 변환이 안 됨.
+            if format == SourceFormat::Jpeg
+                && self
+                    .args_for(format)
+                    .is_some_and(|args| decoder::keeps_jpeg(args))
+            {
+                return Err(format!(
+                    "strip.{} = icc can not be used with --lossless_jpeg=1, the jpg would be decoded to a png. set --lossless_jpeg=0 in args.{} or remove icc",
+                    key, key
+                ));
+            }
+            //cjxl 이 직접 읽는 포맷은 png 를 거치면 빼라고 하지 않은 exif, xmp 도 빠짐.
+            if format.cjxl_reads_directly()
+                && !(strip.contains(&MetadataKind::Exif) && strip.contains(&MetadataKind::Xmp))
+            {
+                return Err(format!(
+                    "strip.{} = icc also drops exif and xmp, the image goes through a png. add exif and xmp to strip.{}",
+                    key, key
+                ));
+            }
         }
+        Ok(())
     }
 
-    fn update_list_element(&mut self, folder_path: &PathBuf) {
-        if let Some(value) = self.worklist.get_mut(folder_path) {
-            *value = true;
+    fn archive_kind(&self, folder_path: &Path) -> ArchiveKind {
+        if self.comic || comic::is_comic_folder(folder_path, &self.comic_folders) {
+            ArchiveKind::Cbz
         } else {
-            panic!("Failed to update worklist");
+            ArchiveKind::Zip
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum SizePolicy {
+    //항상 jxl.
+    #[default]
+    Jxl,
+    //변환하지 않고 항상 원본.
+    Source,
+    //jxl 이 min_savings 이상 작을 때만 jxl, 아니면 원본.
+    Smaller,
+}
+
+impl SizePolicy {
+    fn keep_jxl(self, source_size: u64, jxl_size: u64, min_savings: f64) -> bool {
+        match self {
+            SizePolicy::Jxl => true,
+            SizePolicy::Source => false,
+            SizePolicy::Smaller => {
+                jxl_size < source_size
+                    && (source_size - jxl_size) as f64 * 100.0 >= source_size as f64 * min_savings
+            }
+        }
+    }
+}
+
+enum JXL {
+    ExistFromBegin(PathBuf),
+    //jxl 이 원본보다 크거나 충분히 작지 않아서 원본을 그대로 씀.
+    SourceKept(PathBuf),
+    Converted {
+        image_path: PathBuf,
+        jxl_path: PathBuf,
+    },
+    //필터에 걸려 변환하지 않은 원본과 그 이유. zip 에 넣지 않고, 폴더도 지우지 않음.
+    Skipped(PathBuf, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_jxl_follows_size_policy_and_min_savings() {
+        assert!(SizePolicy::Jxl.keep_jxl(100, 200, 10.0));
+        assert!(!SizePolicy::Source.keep_jxl(100, 1, 0.0));
+
+        //min_savings 0 은 1 바이트라도 작으면 jxl.
+        assert!(SizePolicy::Smaller.keep_jxl(100, 99, 0.0));
+        assert!(!SizePolicy::Smaller.keep_jxl(100, 100, 0.0));
+        assert!(!SizePolicy::Smaller.keep_jxl(100, 101, 0.0));
+
+        //딱 min_savings 만큼 작으면 jxl.
+        assert!(SizePolicy::Smaller.keep_jxl(100, 90, 10.0));
+        assert!(!SizePolicy::Smaller.keep_jxl(100, 91, 10.0));
+        assert!(SizePolicy::Smaller.keep_jxl(100, 0, 100.0));
+        assert!(!SizePolicy::Smaller.keep_jxl(100, 1, 100.0));
+        assert!(!SizePolicy::Smaller.keep_jxl(0, 0, 0.0));
+    }
+
+    #[test]
+    fn archive_root_is_the_folder_at_zip_depth() {
+        let work_folder = Path::new("w");
+        assert_eq!(
+            archive_root(work_folder, Path::new("w/a/b"), 0),
+            Some(PathBuf::from("w"))
+        );
+        assert_eq!(
+            archive_root(work_folder, work_folder, 0),
+            Some(PathBuf::from("w"))
+        );
+
+        assert_eq!(
+            archive_root(work_folder, Path::new("w/a"), 1),
+            Some(PathBuf::from("w/a"))
+        );
+        assert_eq!(
+            archive_root(work_folder, Path::new("w/a/b/c"), 1),
+            Some(PathBuf::from("w/a"))
+        );
+        assert_eq!(archive_root(work_folder, work_folder, 1), None);
+
+        assert_eq!(
+            archive_root(work_folder, Path::new("w/a/b/c"), 2),
+            Some(PathBuf::from("w/a/b"))
+        );
+        assert_eq!(archive_root(work_folder, Path::new("w/a"), 2), None);
+        assert_eq!(archive_root(work_folder, Path::new("x/a/b"), 1), None);
+    }
+}
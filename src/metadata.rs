@@ -0,0 +1,555 @@
+use crate::format::SourceFormat;
+use crate::jxl::{self, BitReader};
+use chrono::{Datelike, Local, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, FileTimes};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use zip::DateTime;
+
+//원본의 메타데이터 종류. 설정 파일의 [strip] 에 쓰는 이름은 config_key.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataKind {
+    Exif,
+    Xmp,
+    Icc,
+}
+
+impl MetadataKind {
+    pub const ALL: [MetadataKind; 3] = [MetadataKind::Exif, MetadataKind::Xmp, MetadataKind::Icc];
+
+    pub fn config_key(self) -> &'static str {
+        match self {
+            MetadataKind::Exif => "exif",
+            MetadataKind::Xmp => "xmp",
+            MetadataKind::Icc => "icc",
+        }
+    }
+}
+
+//파일에서 찾은 메타데이터.
+#[derive(Default, Debug)]
+struct Found {
+    exif: bool,
+    xmp: bool,
+    icc: bool,
+    //icc 가 sRGB 프로필로 보임. cjxl 은 sRGB 프로필을 기본 색 공간으로 바꿔서 저장할 수 있음.
+    srgb_icc: bool,
+}
+
+impl Found {
+    fn has(&self, kind: MetadataKind) -> bool {
+        match kind {
+            MetadataKind::Exif => self.exif,
+            MetadataKind::Xmp => self.xmp,
+            MetadataKind::Icc => self.icc,
+        }
+    }
+}
+
+//원본에 있던 메타데이터가 jxl 에도 있는지, strip 한 것은 빠졌는지 확인. 문제를 하나씩 돌려줌.
+//메타데이터를 읽을 수 없는 포맷은 확인하지 않음.
+pub fn check_metadata(
+    source: &[u8],
+    source_format: SourceFormat,
+    jxl: &[u8],
+    strip: &[MetadataKind],
+) -> Vec<String> {
+    let Some(source_found) = source_metadata(source, source_format) else {
+        return vec![];
+    };
+    let jxl_found = jxl_metadata(jxl);
+    let mut problems = vec![];
+    for kind in MetadataKind::ALL {
+        if !source_found.has(kind) {
+            continue;
+        }
+        //코드스트림 헤더를 읽지 못하면 icc 는 확인하지 않음.
+        let Some(in_jxl) = (match kind {
+            MetadataKind::Icc => jxl_found.icc,
+            MetadataKind::Exif => Some(jxl_found.exif),
+            MetadataKind::Xmp => Some(jxl_found.xmp),
+        }) else {
+            continue;
+        };
+        let stripped = strip.contains(&kind);
+        if stripped && in_jxl {
+            problems.push(format!("{} is not stripped", kind.config_key()));
+        } else if !stripped && !in_jxl {
+            if kind == MetadataKind::Icc && source_found.srgb_icc {
+                continue;
+            }
+            problems.push(format!("{} is lost", kind.config_key()));
+        }
+    }
+    problems
+}
+
+//png 로 풀 때 빠지는 icc 가 sRGB 가 아닌지. sRGB 프로필은 빠져도 색이 같음.
+//메타데이터를 읽을 수 없는 포맷은 false.
+pub fn has_non_srgb_icc(data: &[u8], source_format: SourceFormat) -> bool {
+    source_metadata(data, source_format).is_some_and(|found| found.icc && !found.srgb_icc)
+}
+
+//원본의 메타데이터. jpeg, png, webp, tiff, bmp 만 읽음.
+fn source_metadata(data: &[u8], source_format: SourceFormat) -> Option<Found> {
+    match source_format {
+        SourceFormat::Jpeg => Some(jpeg_metadata(data)),
+        SourceFormat::Png => Some(png_metadata(data)),
+        SourceFormat::WebP => Some(webp_metadata(data)),
+        SourceFormat::Tiff => Some(tiff_metadata(data)),
+        SourceFormat::Bmp => Some(bmp_metadata(data)),
+        _ => None,
+    }
+}
+
+//SOS 앞의 APP 세그먼트. APP1 은 Exif 나 XMP, APP2 는 ICC.
+fn jpeg_metadata(data: &[u8]) -> Found {
+    const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+    let mut found = Found::default();
+    let mut rest = data.get(2..).unwrap_or_default();
+    while rest.len() >= 4 && rest[0] == 0xFF {
+        let marker = rest[1];
+        //SOS 뒤는 이미지 데이터.
+        if marker == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        let Some(payload) = rest.get(4..2 + length) else {
+            break;
+        };
+        match marker {
+            0xE1 if payload.starts_with(b"Exif\0\0") => found.exif = true,
+            0xE1 if payload.starts_with(XMP_SIGNATURE) => found.xmp = true,
+            0xE2 if payload.starts_with(b"ICC_PROFILE\0") => {
+                found.icc = true;
+                found.srgb_icc |= contains(payload, b"sRGB");
+            }
+            _ => {}
+        }
+        rest = &rest[2 + length..];
+    }
+    found
+}
+
+//eXIf, iCCP, XMP 가 든 iTXt 청크.
+fn png_metadata(data: &[u8]) -> Found {
+    let mut found = Found::default();
+    let mut rest = data.get(8..).unwrap_or_default();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let chunk_type = &rest[4..8];
+        let Some(payload) = rest.get(8..8 + length) else {
+            break;
+        };
+        match chunk_type {
+            b"eXIf" => found.exif = true,
+            b"iTXt" if payload.starts_with(b"XML:com.adobe.xmp\0") => found.xmp = true,
+            //icc 는 압축되어 있으므로 앞의 프로필 이름만 봄.
+            b"iCCP" => {
+                found.icc = true;
+                found.srgb_icc |= contains(
+                    payload.split(|&byte| byte == 0).next().unwrap_or_default(),
+                    b"sRGB",
+                );
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        let Some(next) = rest.get(12 + length..) else {
+            break;
+        };
+        rest = next;
+    }
+    found
+}
+
+//RIFF 안의 EXIF, XMP, ICCP 청크. 청크 크기는 짝수로 맞춰짐.
+fn webp_metadata(data: &[u8]) -> Found {
+    let mut found = Found::default();
+    let mut rest = data.get(12..).unwrap_or_default();
+    while rest.len() >= 8 {
+        let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let Some(payload) = rest.get(8..8 + size) else {
+            break;
+        };
+        match &rest[..4] {
+            b"EXIF" => found.exif = true,
+            b"XMP " => found.xmp = true,
+            b"ICCP" => {
+                found.icc = true;
+                found.srgb_icc |= contains(payload, b"sRGB");
+            }
+            _ => {}
+        }
+        let Some(next) = rest.get(8 + size + size % 2..) else {
+            break;
+        };
+        rest = next;
+    }
+    found
+}
+
+//첫 IFD 의 태그. 700 은 XMP, 34665 는 Exif IFD, 34675 는 ICC.
+fn tiff_metadata(data: &[u8]) -> Found {
+    let mut found = Found::default();
+    let read_u16 = |bytes: &[u8], little: bool| {
+        let bytes = [bytes[0], bytes[1]];
+        if little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    };
+    let read_u32 = |bytes: &[u8], little: bool| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    };
+    let little = match data.get(..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        _ => return found,
+    };
+    let Some(ifd) = data.get(4..8).map(|bytes| read_u32(bytes, little) as usize) else {
+        return found;
+    };
+    let Some(count) = data.get(ifd..ifd + 2).map(|bytes| read_u16(bytes, little)) else {
+        return found;
+    };
+    for index in 0..count as usize {
+        let Some(entry) = data.get(ifd + 2 + index * 12..ifd + 14 + index * 12) else {
+            break;
+        };
+        match read_u16(&entry[..2], little) {
+            700 => found.xmp = true,
+            34665 => found.exif = true,
+            34675 => {
+                found.icc = true;
+                //UNDEFINED 4바이트보다 길면 값 자리에 위치가 있음.
+                let length = read_u32(&entry[4..8], little) as usize;
+                let offset = read_u32(&entry[8..12], little) as usize;
+                let profile = match length {
+                    0..=4 => &entry[8..8 + length],
+                    _ => data.get(offset..offset + length).unwrap_or_default(),
+                };
+                found.srgb_icc |= contains(profile, b"sRGB");
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+//BITMAPV5HEADER 의 bV5CSType 이 PROFILE_EMBEDDED('MBED') 면 icc 가 들어있음.
+//bmp 에는 exif, xmp 가 없음.
+fn bmp_metadata(data: &[u8]) -> Found {
+    let mut found = Found::default();
+    let Some(header) = data.get(14..14 + 124) else {
+        return found;
+    };
+    if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) < 124 {
+        return found;
+    }
+    if &header[56..60] == b"DEBM" {
+        found.icc = true;
+        //프로필 위치는 헤더 시작 기준.
+        let offset = u32::from_le_bytes([header[112], header[113], header[114], header[115]]);
+        let length = u32::from_le_bytes([header[116], header[117], header[118], header[119]]);
+        let start = 14 + offset as usize;
+        found.srgb_icc = data
+            .get(start..start + length as usize)
+            .is_some_and(|profile| contains(profile, b"sRGB"));
+    }
+    found
+}
+
+//jxl 에서 찾은 메타데이터. icc 는 코드스트림 헤더를 읽지 못하면 None.
+struct JxlFound {
+    exif: bool,
+    xmp: bool,
+    icc: Option<bool>,
+}
+
+//Exif 와 XMP 는 컨테이너 상자(brob 으로 압축되었을 수도 있음), icc 는 코드스트림 헤더에 있음.
+fn jxl_metadata(data: &[u8]) -> JxlFound {
+    let mut found = JxlFound {
+        exif: false,
+        xmp: false,
+        icc: None,
+    };
+    for (box_type, payload) in jxl::boxes(data) {
+        //brob 은 4바이트 원래 상자 종류 뒤에 brotli 로 압축한 내용이 옴.
+        let box_type = match box_type {
+            b"brob" => payload.get(..4).unwrap_or_default(),
+            _ => box_type,
+        };
+        match box_type {
+            b"Exif" => found.exif = true,
+            b"xml " => found.xmp = true,
+            _ => {}
+        }
+    }
+    found.icc = jxl::codestream(data).and_then(codestream_has_icc);
+    found
+}
+
+//ImageMetadata 를 ColourEncoding 까지 읽어서 icc 가 들어있는지 봄.
+//기본값이 아닌 색 공간(enum)도 원본의 색 정보가 남은 것으로 봄.
+//기본값이 아닌 부가 채널이 있으면 읽지 않고 None.
+fn codestream_has_icc(data: &[u8]) -> Option<bool> {
+    let mut bits = BitReader::new(data);
+    bits.skip_size_header()?;
+    //ImageMetadata.all_default 면 sRGB.
+    if bits.read(1)? == 1 {
+        return Some(false);
+    }
+    let extra_fields = bits.read(1)? == 1;
+    if extra_fields {
+        //orientation
+        bits.read(3)?;
+        //intrinsic size
+        if bits.read(1)? == 1 {
+            bits.skip_size_header()?;
+        }
+        //PreviewHeader
+        if bits.read(1)? == 1 {
+            let div8 = bits.read(1)? == 1;
+            let skip_preview_size = |bits: &mut BitReader| {
+                if div8 {
+                    bits.read_u32([(16, 0), (32, 0), (1, 5), (33, 9)])
+                } else {
+                    bits.read_u32([(1, 6), (65, 8), (321, 10), (1345, 12)])
+                }
+            };
+            skip_preview_size(&mut bits)?;
+            if bits.read(3)? == 0 {
+                skip_preview_size(&mut bits)?;
+            }
+        }
+        //AnimationHeader
+        if bits.read(1)? == 1 {
+            bits.read_u32([(100, 0), (1000, 0), (1, 10), (1, 30)])?;
+            bits.read_u32([(1, 0), (1001, 0), (1, 8), (1, 10)])?;
+            bits.read_u32([(0, 0), (0, 3), (0, 16), (0, 32)])?;
+            bits.read(1)?;
+        }
+    }
+    //BitDepth
+    if bits.read(1)? == 1 {
+        bits.read_u32([(32, 0), (16, 0), (24, 0), (1, 6)])?;
+        bits.read(4)?;
+    } else {
+        bits.read_u32([(8, 0), (10, 0), (12, 0), (1, 6)])?;
+    }
+    //modular_16_bit_buffer_sufficient
+    bits.read(1)?;
+    let extra_channels = bits.read_u32([(0, 0), (1, 0), (2, 4), (1, 12)])?;
+    for _ in 0..extra_channels {
+        if bits.read(1)? == 0 {
+            return None;
+        }
+    }
+    //xyb_encoded
+    bits.read(1)?;
+    //ColourEncoding.all_default 면 sRGB.
+    if bits.read(1)? == 1 {
+        return Some(false);
+    }
+    if bits.read(1)? == 1 {
+        return Some(true);
+    }
+    Some(!is_srgb(&mut bits)?)
+}
+
+//icc 없이 enum 으로 적은 색 공간이 sRGB 인지. 원본 icc 를 enum 으로 옮겼으면 sRGB 가 아님.
+fn is_srgb(bits: &mut BitReader) -> Option<bool> {
+    //Enum 필드는 U32(Val(0), Val(1), BitsOffset(4, 2), BitsOffset(6, 18)).
+    let mut read_enum = || bits.read_u32([(0, 0), (1, 0), (2, 4), (18, 6)]);
+    //colour_space RGB(0), white_point D65(1), primaries sRGB(1).
+    if read_enum()? != 0 || read_enum()? != 1 || read_enum()? != 1 {
+        return Some(false);
+    }
+    //have_gamma 가 아니면 transfer_function sRGB(13).
+    if bits.read(1)? == 1 {
+        return Some(false);
+    }
+    Some(bits.read_u32([(0, 0), (1, 0), (2, 4), (18, 6)])? == 13)
+}
+
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+}
+
+//원본의 수정 시간과 접근 시간을 jxl 에 옮김. 사진을 찍은 날짜로 정렬하는 프로그램이 씀.
+pub fn copy_file_times(source_path: &Path, target_path: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(source_path)?;
+    let mut times = FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    File::options()
+        .write(true)
+        .open(target_path)?
+        .set_times(times)
+}
+
+//zip 항목의 수정 시간. MS-DOS 시간은 지역 시간이고 2초 단위이며 1980 ~ 2107 년만 됨.
+pub fn zip_date_time(time: SystemTime) -> Option<DateTime> {
+    let local = chrono::DateTime::<Local>::from(time);
+    DateTime::from_date_and_time(
+        u16::try_from(local.year()).ok()?,
+        local.month() as u8,
+        local.day() as u8,
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+    )
+    .ok()
+}
+
+//zip extra field 에서 id 가 같은 필드의 데이터. id(2) + 크기(2) + 데이터가 이어져 있음.
+pub fn zip_extra_field(extra_data: &[u8], id: u16) -> Option<&[u8]> {
+    let mut rest = extra_data;
+    while rest.len() >= 4 {
+        let field_id = u16::from_le_bytes([rest[0], rest[1]]);
+        let size = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let field = rest.get(4..4 + size)?;
+        if field_id == id {
+            return Some(field);
+        }
+        rest = &rest[4 + size..];
+    }
+    None
+}
+
+//zip 항목의 수정 시간. extended timestamp 가 있으면 그것을, 없으면 지역 시간인 MS-DOS 시간을 씀.
+pub fn zip_entry_modified(extra_data: &[u8], date_time: DateTime) -> Option<SystemTime> {
+    //플래그의 첫 비트가 수정 시간.
+    if let Some(field) = zip_extra_field(extra_data, 0x5455) {
+        if field.len() >= 5 && field[0] & 1 == 1 {
+            let seconds = i32::from_le_bytes([field[1], field[2], field[3], field[4]]);
+            return Some(if seconds >= 0 {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(seconds as u64)
+            } else {
+                SystemTime::UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs() as u64)
+            });
+        }
+    }
+    let local = Local
+        .with_ymd_and_hms(
+            date_time.year() as i32,
+            date_time.month() as u32,
+            date_time.day() as u32,
+            date_time.hour() as u32,
+            date_time.minute() as u32,
+            date_time.second() as u32,
+        )
+        .earliest()?;
+    Some(local.into())
+}
+
+//zip 의 extended timestamp 부가 필드(0x5455). 초 단위 UTC 유닉스 시간.
+//로컬 헤더에는 수정 시간과 접근 시간, 중앙 디렉터리에는 수정 시간만 넣음.
+pub fn extended_timestamp(
+    modified: SystemTime,
+    accessed: Option<SystemTime>,
+    local: bool,
+) -> Vec<u8> {
+    let unix_time = |time: SystemTime| {
+        let seconds = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        };
+        seconds.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    };
+    //플래그는 로컬 헤더에 든 값. 중앙 디렉터리도 같은 플래그를 씀.
+    let flags: u8 = if accessed.is_some() { 0b11 } else { 0b01 };
+    let accessed = accessed.filter(|_| local);
+    let size: u16 = if accessed.is_some() { 9 } else { 5 };
+    let mut field = vec![];
+    field.extend_from_slice(&0x5455u16.to_le_bytes());
+    field.extend_from_slice(&size.to_le_bytes());
+    field.push(flags);
+    field.extend_from_slice(&unix_time(modified).to_le_bytes());
+    if let Some(accessed) = accessed {
+        field.extend_from_slice(&unix_time(accessed).to_le_bytes());
+    }
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //small SizeHeader (8x8) 뒤의 ImageMetadata 가 all_default.
+    const DEFAULT_CODESTREAM: [u8; 2] = [0x41, 0x02];
+    //ImageMetadata 는 기본값이 아니고 ColourEncoding 에 want_icc.
+    const ICC_CODESTREAM: [u8; 3] = [0x41, 0x40, 0x0A];
+
+    fn tiff_with_icc(profile: &[u8; 4]) -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&34675u16.to_le_bytes());
+        data.extend_from_slice(&7u16.to_le_bytes());
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(profile);
+        data
+    }
+
+    #[test]
+    fn codestream_has_icc_reads_colour_encoding() {
+        assert_eq!(codestream_has_icc(&DEFAULT_CODESTREAM), Some(false));
+        assert_eq!(codestream_has_icc(&ICC_CODESTREAM), Some(true));
+    }
+
+    #[test]
+    fn codestream_has_icc_is_none_on_truncated_header() {
+        assert_eq!(codestream_has_icc(&DEFAULT_CODESTREAM[..1]), None);
+        assert_eq!(codestream_has_icc(&ICC_CODESTREAM[..2]), None);
+        assert_eq!(codestream_has_icc(&[]), None);
+    }
+
+    #[test]
+    fn unreadable_jxl_icc_is_not_reported() {
+        let source = tiff_with_icc(b"abcd");
+        let mut jxl = vec![0xFF, 0x0A];
+        jxl.extend_from_slice(&ICC_CODESTREAM);
+        assert!(check_metadata(&source, SourceFormat::Tiff, &jxl, &[]).is_empty());
+        assert_eq!(
+            check_metadata(&source, SourceFormat::Tiff, &jxl, &[MetadataKind::Icc]),
+            ["icc is not stripped"]
+        );
+        //잘린 코드스트림은 icc 를 확인하지 않음.
+        assert!(check_metadata(&source, SourceFormat::Tiff, &jxl[..3], &[]).is_empty());
+        jxl.truncate(2);
+        jxl.extend_from_slice(&DEFAULT_CODESTREAM);
+        assert_eq!(
+            check_metadata(&source, SourceFormat::Tiff, &jxl, &[]),
+            ["icc is lost"]
+        );
+    }
+
+    #[test]
+    fn tiff_icc_is_read_from_first_ifd() {
+        assert!(has_non_srgb_icc(
+            &tiff_with_icc(b"abcd"),
+            SourceFormat::Tiff
+        ));
+        assert!(!has_non_srgb_icc(
+            &tiff_with_icc(b"sRGB"),
+            SourceFormat::Tiff
+        ));
+        //IFD 가 잘리면 태그를 읽지 않음.
+        let data = tiff_with_icc(b"abcd");
+        assert!(!has_non_srgb_icc(&data[..20], SourceFormat::Tiff));
+        assert!(!has_non_srgb_icc(&data[..9], SourceFormat::Tiff));
+        assert!(!has_non_srgb_icc(b"II*", SourceFormat::Tiff));
+    }
+}
@@ -0,0 +1,447 @@
+use crate::archive::{archive_path, entry_name, ArchiveKind};
+use crate::comic::{self, COMIC_INFO_NAME};
+use crate::config::{self, FolderConfig};
+use crate::format::SourceFormat;
+use crate::worklist::{EncodeSetting, WorkInfo};
+use crate::{
+    archive_root, finalize_xxhash, has_source_image, is_image_file, is_temp_file, scan_folders,
+    skip_reason, skipped_folder, JxlArgs, SizePolicy,
+};
+use jwalk::WalkDir;
+use log::info;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+//--dry-run 으로 만드는 작업 계획. 디스크에는 아무것도 쓰지 않음.
+#[derive(Serialize, Default)]
+pub struct Plan {
+    pub work_folder: PathBuf,
+    pub conversions: Vec<PlannedConversion>,
+    pub archives: Vec<PlannedArchive>,
+    pub deletions: Vec<PlannedDeletion>,
+    pub skipped: Vec<PlannedSkip>,
+    pub totals: PlanTotals,
+}
+
+#[derive(Serialize)]
+pub struct PlannedConversion {
+    pub source: PathBuf,
+    pub jxl: PathBuf,
+    pub format: &'static str,
+    pub args: Vec<String>,
+    pub source_size: u64,
+}
+
+#[derive(Serialize)]
+pub struct PlannedArchive {
+    pub path: PathBuf,
+    pub kind: &'static str,
+    //zip 안의 이름.
+    pub members: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PlannedDeletion {
+    pub path: PathBuf,
+    pub kind: DeletionKind,
+    //false 면 휴지통에 버림.
+    pub permanent: bool,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionKind {
+    //delete_source_image 로 지우는 원본.
+    SourceImage,
+    //zip 에 넣은 뒤 지우는 jxl.
+    PackedFile,
+    //delete_folder 로 지우는 폴더.
+    Folder,
+}
+
+#[derive(Serialize)]
+pub struct PlannedSkip {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct PlanTotals {
+    pub conversions: usize,
+    pub source_bytes: u64,
+    pub archives: usize,
+    pub archive_members: usize,
+    pub deletions: usize,
+    pub skipped: usize,
+}
+
+//하나의 zip 으로 묶을 폴더들. convert_work_folder 의 ArchiveGroup 과 같음.
+#[derive(Default)]
+struct PlannedGroup {
+    files: Vec<PathBuf>,
+    //zip 에 넣은 뒤에도 남는 파일.
+    kept: Vec<PathBuf>,
+    failed: bool,
+    //필터에 걸려 남는 원본이 있으면 폴더를 지우지 않음.
+    skipped: bool,
+}
+
+impl Plan {
+    fn new(work_folder: &Path) -> Self {
+        Plan {
+            work_folder: work_folder.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    fn delete(&mut self, path: PathBuf, kind: DeletionKind, cjxl_args: &JxlArgs) {
+        self.deletions.push(PlannedDeletion {
+            path,
+            kind,
+            permanent: cjxl_args.dont_use_trashcan_just_delete,
+        });
+    }
+
+    fn skip(&mut self, path: &Path, reason: impl Into<String>) {
+        self.skipped.push(PlannedSkip {
+            path: path.to_path_buf(),
+            reason: reason.into(),
+        });
+    }
+
+    fn archive(
+        &mut self,
+        folder_path: &Path,
+        mut files: Vec<PathBuf>,
+        kept: &[PathBuf],
+        cjxl_args: &JxlArgs,
+    ) {
+        let kind = cjxl_args.archive_kind(folder_path);
+        files.sort();
+        files.dedup();
+        let mut members: Vec<String> = files
+            .iter()
+            .map(|file| entry_name(folder_path, file))
+            .collect();
+        if kind == ArchiveKind::Cbz {
+            members.sort_by(|a, b| comic::natural_cmp(a, b));
+            members.push(COMIC_INFO_NAME.to_string());
+        }
+        self.archives.push(PlannedArchive {
+            path: archive_path(folder_path, kind),
+            kind: kind.extension(),
+            members,
+        });
+        for file in files {
+            if !kept.contains(&file) {
+                self.delete(file, DeletionKind::PackedFile, cjxl_args);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        self.totals = PlanTotals {
+            conversions: self.conversions.len(),
+            source_bytes: self.conversions.iter().map(|c| c.source_size).sum(),
+            archives: self.archives.len(),
+            archive_members: self.archives.iter().map(|a| a.members.len()).sum(),
+            deletions: self.deletions.len(),
+            skipped: self.skipped.len(),
+        };
+        self
+    }
+
+    //사람이 읽는 계획.
+    pub fn print(&self) {
+        info!("dry run: {}", self.work_folder.display());
+        for conversion in &self.conversions {
+            info!(
+                "convert: {} -> {} ({}_args={:?})",
+                conversion.source.display(),
+                conversion.jxl.display(),
+                conversion.format,
+                conversion.args
+            );
+        }
+        for archive in &self.archives {
+            info!(
+                "{}: {} ({} files)",
+                archive.kind,
+                archive.path.display(),
+                archive.members.len()
+            );
+            for member in &archive.members {
+                info!("    {}", member);
+            }
+        }
+        for deletion in &self.deletions {
+            info!(
+                "{} {:?}: {}",
+                if deletion.permanent {
+                    "delete"
+                } else {
+                    "trash"
+                },
+                deletion.kind,
+                deletion.path.display()
+            );
+        }
+        for skip in &self.skipped {
+            info!("skip: {} ({})", skip.path.display(), skip.reason);
+        }
+        let totals = &self.totals;
+        info!(
+            "total: {} conversions ({} bytes), {} archives ({} files), {} deletions, {} skipped",
+            totals.conversions,
+            totals.source_bytes,
+            totals.archives,
+            totals.archive_members,
+            totals.deletions,
+            totals.skipped
+        );
+    }
+}
+
+//convert_work_folder 와 같은 순서로 폴더를 돌면서 할 일만 모음.
+pub fn plan_convert(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+    encoder_version: &str,
+    reencode_stale: bool,
+) -> Result<Plan, Box<dyn Error>> {
+    let mut plan = Plan::new(work_folder);
+    let folder_list = scan_folders(work_folder, cjxl_args, folder_config)?;
+    let scanned: HashSet<PathBuf> = folder_list.iter().map(|(path, _)| path.clone()).collect();
+    let setting_ids: HashMap<&Path, u64> = folder_list
+        .iter()
+        .map(|(path, folder_args)| {
+            (
+                path.as_path(),
+                EncodeSetting::new(folder_args, encoder_version).id(),
+            )
+        })
+        .collect();
+    let work_info = WorkInfo::load(work_folder);
+    let stale_folders = work_info
+        .as_ref()
+        .map(|work_info| {
+            work_info.stale_folders(|path| {
+                setting_ids
+                    .get(path)
+                    .copied()
+                    .unwrap_or_else(|| EncodeSetting::new(cjxl_args, encoder_version).id())
+            })
+        })
+        .unwrap_or_default();
+
+    let mut groups: HashMap<PathBuf, PlannedGroup> = HashMap::new();
+    for (folder_path, folder_args) in &folder_list {
+        let folder_path = folder_path.clone();
+        let cjxl_args: &JxlArgs = folder_args;
+        let setting_id = setting_ids[folder_path.as_path()];
+        let done = work_info
+            .as_ref()
+            .is_some_and(|work_info| work_info.worklist.get(&folder_path) == Some(&true));
+        if done {
+            if !(reencode_stale && stale_folders.binary_search(&folder_path).is_ok()) {
+                plan.skip(&folder_path, "already done");
+                continue;
+            }
+            if !has_source_image(&folder_path) {
+                plan.skip(&folder_path, "stale but no source image left");
+                continue;
+            }
+        }
+
+        let mut group = PlannedGroup::default();
+        let mut entries: Vec<PathBuf> = folder_path
+            .read_dir()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let ext = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+                path.is_file()
+                    && ext != "zip"
+                    && ext != "cbz"
+                    && ext != "part"
+                    && !is_temp_file(path)
+                    && !config::is_folder_config(path)
+            })
+            .collect();
+        entries.sort();
+        for path in entries {
+            if let Some(reason) = skip_reason(work_folder, cjxl_args, &path) {
+                plan.skip(&path, reason);
+                group.skipped = true;
+                continue;
+            }
+            match is_image_file(&path) {
+                Ok(image_format) => {
+                    let format = SourceFormat::from_image_format(image_format)
+                        .expect("is_image_file checks the format");
+                    let jxl_path = path.with_extension("jxl");
+                    //지난번에 끝낸 파일은 다시 변환하지 않음.
+                    let recorded = work_info
+                        .as_ref()
+                        .and_then(|work_info| work_info.files.get(&path));
+                    //읽을 수 없는 파일은 변환에서도 실패하므로 계획에 남기고 폴더는 묶지 않음.
+                    let source_hash = match recorded.map(|_| finalize_xxhash(&path)) {
+                        Some(Ok(source_hash)) => source_hash,
+                        Some(Err(err)) => {
+                            plan.skip(&path, format!("{}, folder is not packed", err));
+                            group.failed = true;
+                            continue;
+                        }
+                        None => 0,
+                    };
+                    if recorded.is_some_and(|state| state.is_source_kept(source_hash, setting_id))
+                        || cjxl_args.size_policy == SizePolicy::Source
+                    {
+                        group.files.push(path.clone());
+                        if !cjxl_args.delete_source_image_plag {
+                            group.kept.push(path.clone());
+                        }
+                        continue;
+                    }
+                    if recorded
+                        .is_some_and(|state| state.is_converted(source_hash, setting_id, &jxl_path))
+                    {
+                        plan.skip(&path, "already converted");
+                    } else {
+                        plan.conversions.push(PlannedConversion {
+                            source: path.clone(),
+                            jxl: jxl_path.clone(),
+                            format: format.config_key(),
+                            args: cjxl_args.args_for(format).cloned().unwrap_or_default(),
+                            source_size: path.metadata().map(|m| m.len()).unwrap_or_default(),
+                        });
+                    }
+                    if cjxl_args.delete_source_image_plag {
+                        plan.delete(path.clone(), DeletionKind::SourceImage, cjxl_args);
+                    }
+                    group.files.push(jxl_path);
+                }
+                Err(err) if err.contains("This file is skip") => group.files.push(path),
+                Err(_) => {
+                    plan.skip(&path, "not an image, folder is not packed");
+                    group.failed = true;
+                }
+            }
+        }
+
+        let root = cjxl_args
+            .zip_depth
+            .filter(|_| cjxl_args.make_zip_plag)
+            .and_then(|zip_depth| archive_root(work_folder, &folder_path, zip_depth));
+        let group = match root {
+            //하위 폴더는 archive_root 차례까지 모아둠.
+            Some(root) if root != folder_path => {
+                let parent = groups.entry(root).or_default();
+                parent.files.append(&mut group.files);
+                parent.kept.append(&mut group.kept);
+                parent.failed |= group.failed;
+                parent.skipped |= group.skipped;
+                continue;
+            }
+            Some(root) => {
+                let mut parent = groups.remove(&root).unwrap_or_default();
+                parent.files.append(&mut group.files);
+                parent.kept.append(&mut group.kept);
+                parent.failed |= group.failed;
+                parent.skipped |= group.skipped;
+                parent
+            }
+            None => group,
+        };
+
+        if group.failed || group.files.is_empty() || !cjxl_args.make_zip_plag {
+            continue;
+        }
+        plan.archive(&folder_path, group.files, &group.kept, cjxl_args);
+        if cjxl_args.delete_folder_plag
+            && (group.skipped || skipped_folder(&folder_path, &scanned).is_some())
+        {
+            plan.skip(&folder_path, "skipped image or folder left, folder is kept");
+        } else if cjxl_args.delete_folder_plag {
+            plan.delete(folder_path, DeletionKind::Folder, cjxl_args);
+        }
+    }
+    if cjxl_args.size_policy == SizePolicy::Smaller {
+        plan.skip(
+            work_folder,
+            "size_policy=smaller: jxl or source is chosen after converting, archives list the jxl",
+        );
+    }
+    Ok(plan.finish())
+}
+
+//zip_work_folder 와 같은 순서로 할 일만 모음.
+pub fn plan_zip(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+) -> Result<Plan, Box<dyn Error>> {
+    let mut plan = Plan::new(work_folder);
+    let folder_list = scan_folders(work_folder, cjxl_args, folder_config)?;
+    let scanned: HashSet<PathBuf> = folder_list.iter().map(|(path, _)| path.clone()).collect();
+    for (folder_path, folder_args) in folder_list {
+        let cjxl_args: &JxlArgs = &folder_args;
+        let files: Vec<PathBuf> = match cjxl_args
+            .zip_depth
+            .and_then(|zip_depth| archive_root(work_folder, &folder_path, zip_depth))
+        {
+            Some(root) if root != folder_path => continue,
+            Some(_) => WalkDir::new(&folder_path)
+                .sort(true)
+                .into_iter()
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+            None => folder_path
+                .read_dir()?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+        };
+
+        let mut jxl_files = vec![];
+        let mut image_left = false;
+        for path in files {
+            if !path.is_file() || is_temp_file(&path) {
+                continue;
+            }
+            if !path.parent().is_some_and(|parent| scanned.contains(parent)) {
+                continue;
+            }
+            let ext = path
+                .extension()
+                .and_then(OsStr::to_str)
+                .unwrap_or_default()
+                .to_lowercase();
+            if ext == "jxl" {
+                jxl_files.push(path);
+            } else if SourceFormat::from_extension(&ext).is_some() {
+                image_left = true;
+            }
+        }
+        if jxl_files.is_empty() {
+            continue;
+        }
+        plan.archive(&folder_path, jxl_files, &[], cjxl_args);
+        if cjxl_args.delete_folder_plag {
+            if image_left || skipped_folder(&folder_path, &scanned).is_some() {
+                plan.skip(
+                    &folder_path,
+                    "not converted image or skipped folder left, folder is kept",
+                );
+            } else {
+                plan.delete(folder_path, DeletionKind::Folder, cjxl_args);
+            }
+        }
+    }
+    Ok(plan.finish())
+}
@@ -0,0 +1,193 @@
+use log::info;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+//터미널에 다시 그리는 간격.
+const DRAW_INTERVAL: Duration = Duration::from_millis(200);
+//터미널이 아니면 이 간격마다 로그 한 줄.
+const LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+//지금 터미널 맨 아래에 그려둔 진행 줄. 로그를 찍을 때 지웠다가 다시 그림.
+static LINE: Mutex<Option<String>> = Mutex::new(None);
+
+//변환 진행 상황. 합계는 변환을 시작하기 전에 폴더를 읽으면서 셈.
+pub struct Progress {
+    files_total: usize,
+    folders_total: usize,
+    bytes_total: u64,
+    files_done: AtomicUsize,
+    files_failed: AtomicUsize,
+    folders_done: AtomicUsize,
+    input_bytes: AtomicU64,
+    output_bytes: AtomicU64,
+    start: Instant,
+    terminal: bool,
+    last_shown: Mutex<Instant>,
+}
+
+impl Progress {
+    pub fn new(files_total: usize, folders_total: usize, bytes_total: u64) -> Self {
+        info!(
+            "{} images ({}) in {} folders to convert",
+            files_total,
+            human_bytes(bytes_total),
+            folders_total
+        );
+        let start = Instant::now();
+        Progress {
+            files_total,
+            folders_total,
+            bytes_total,
+            files_done: AtomicUsize::new(0),
+            files_failed: AtomicUsize::new(0),
+            folders_done: AtomicUsize::new(0),
+            input_bytes: AtomicU64::new(0),
+            output_bytes: AtomicU64::new(0),
+            start,
+            //로그는 stdout, 진행 줄은 stderr 에 씀. stdout 을 파일로 돌리면 그 파일에 로그 줄로 남기고,
+            //stderr 만 돌렸을 때도 지우는 공백이 파일에 쌓이지 않도록 둘 다 터미널일 때만 그림.
+            terminal: io::stdout().is_terminal() && io::stderr().is_terminal(),
+            last_shown: Mutex::new(start),
+        }
+    }
+
+    //output_bytes 가 None 이면 실패.
+    pub fn file_done(&self, input_bytes: u64, output_bytes: Option<u64>) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        self.input_bytes.fetch_add(input_bytes, Ordering::Relaxed);
+        match output_bytes {
+            Some(output_bytes) => {
+                self.output_bytes.fetch_add(output_bytes, Ordering::Relaxed);
+            }
+            None => {
+                self.files_failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.show();
+    }
+
+    pub fn folder_done(&self) {
+        self.folders_done.fetch_add(1, Ordering::Relaxed);
+        self.show();
+    }
+
+    //진행 줄을 지우고 마지막 상태를 로그로 남김.
+    pub fn finish(&self) {
+        clear_line(&mut LINE.lock().unwrap());
+        info!("progress: {}", self.line());
+    }
+
+    fn show(&self) {
+        let interval = if self.terminal {
+            DRAW_INTERVAL
+        } else {
+            LOG_INTERVAL
+        };
+        //다른 스레드가 그리고 있으면 건너뜀.
+        let Ok(mut last_shown) = self.last_shown.try_lock() else {
+            return;
+        };
+        if last_shown.elapsed() < interval {
+            return;
+        }
+        *last_shown = Instant::now();
+
+        let line = self.line();
+        if self.terminal {
+            let mut shown = LINE.lock().unwrap();
+            clear_line(&mut shown);
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "{}", line);
+            let _ = stderr.flush();
+            *shown = Some(line);
+        } else {
+            info!("progress: {}", line);
+        }
+    }
+
+    fn line(&self) -> String {
+        let files_done = self.files_done.load(Ordering::Relaxed);
+        let files_failed = self.files_failed.load(Ordering::Relaxed);
+        let input_bytes = self.input_bytes.load(Ordering::Relaxed);
+        let output_bytes = self.output_bytes.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64();
+
+        let mut line = format!("files {}/{}", files_done, self.files_total);
+        if files_failed > 0 {
+            line.push_str(&format!(" ({} failed)", files_failed));
+        }
+        line.push_str(&format!(
+            " | folders {}/{} | {} -> {}",
+            self.folders_done.load(Ordering::Relaxed),
+            self.folders_total,
+            human_bytes(input_bytes),
+            human_bytes(output_bytes)
+        ));
+        if input_bytes > 0 {
+            line.push_str(&format!(
+                " ({:.1}%)",
+                output_bytes as f64 * 100.0 / input_bytes as f64
+            ));
+        }
+        if elapsed > 0.0 {
+            line.push_str(&format!(
+                " | {:.1} files/s {}/s",
+                files_done as f64 / elapsed,
+                human_bytes((input_bytes as f64 / elapsed) as u64)
+            ));
+        }
+        //남은 시간은 처리한 바이트 비율로 어림함.
+        if input_bytes > 0 && self.bytes_total > input_bytes {
+            let eta = elapsed * (self.bytes_total - input_bytes) as f64 / input_bytes as f64;
+            let eta = eta as u64;
+            line.push_str(&format!(
+                " | ETA {:02}:{:02}:{:02}",
+                eta / 3600,
+                eta % 3600 / 60,
+                eta % 60
+            ));
+        }
+        line
+    }
+}
+
+//콘솔 로그. 진행 줄을 지우고 로그를 찍은 뒤 다시 그림.
+pub fn print_log(record: &log::Record) {
+    let shown = LINE.lock().unwrap();
+    let mut stderr = io::stderr().lock();
+    if let Some(line) = &*shown {
+        let _ = write!(stderr, "\r{}\r", " ".repeat(line.chars().count()));
+        let _ = stderr.flush();
+    }
+    let _ = writeln!(io::stdout().lock(), "{}", record.args());
+    if let Some(line) = &*shown {
+        let _ = write!(stderr, "{}", line);
+        let _ = stderr.flush();
+    }
+}
+
+//ANSI 코드를 못 쓰는 콘솔도 있으므로 공백으로 덮어씀.
+fn clear_line(shown: &mut Option<String>) {
+    if let Some(line) = shown.take() {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}\r", " ".repeat(line.chars().count()));
+        let _ = stderr.flush();
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
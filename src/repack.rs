@@ -0,0 +1,323 @@
+use crate::archive::{
+    check_packed_zip, find_zip_files, start_file_with_times, sync_parent_dir, PackedEntry,
+};
+use crate::config::FolderConfig;
+use crate::decoder::{self, Decoder};
+use crate::encoder::Encoder;
+use crate::format::SourceFormat;
+use crate::interrupt::{self, PartialFile};
+use crate::metadata;
+use crate::report::Report;
+use crate::{delete_path, pixels_reason, scan_folders, size_age_reason, JxlArgs};
+use image::io::Reader as ImageReader;
+use image::ImageFormat;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::CompressionMethod::Stored;
+use zip::{ZipArchive, ZipWriter};
+
+//zip 안에서 jxl 로 바꿀 이미지.
+struct EntryImage {
+    index: usize,
+    name: String,
+    jxl_name: String,
+    //임시 폴더에 푼 원본과 만든 jxl.
+    source_path: PathBuf,
+    jxl_path: PathBuf,
+    image_format: ImageFormat,
+}
+
+//작업 폴더 아래의 zip, cbz 안의 이미지를 jxl 로 바꿈. 실패한 zip 수를 돌려줌.
+//zip 마다 그 zip 이 있는 폴더의 설정을 씀.
+pub fn repack_work_folder(
+    work_folder: &Path,
+    cjxl_args: &JxlArgs,
+    folder_config: &FolderConfig,
+    encoder: &dyn Encoder,
+    decoder: Option<&dyn Decoder>,
+    report: &Report,
+) -> Result<usize, Box<dyn Error>> {
+    let folder_list = scan_folders(work_folder, cjxl_args, folder_config)?;
+    let mut failed_zips = 0;
+    for (zip_path, zip_args) in find_zip_files(work_folder, &folder_list) {
+        if interrupt::interrupted() {
+            break;
+        }
+        match repack_zip(&zip_path, &zip_args, encoder, decoder, report) {
+            Ok(0) => debug!("No image to convert: {}", zip_path.display()),
+            Ok(converted) => info!("Repacked: {} ({} images)", zip_path.display(), converted),
+            Err(err) => {
+                warn!(
+                    "Failed to repack: {}\nerror message: {}",
+                    zip_path.display(),
+                    err
+                );
+                failed_zips += 1;
+            }
+        }
+    }
+    Ok(failed_zips)
+}
+
+//이미지를 임시 폴더에 풀어서 병렬로 변환하고, 새 zip 을 쓴 뒤 다시 열어서 확인함.
+//확인이 끝나야 원래 zip 을 휴지통에 넣거나 지우고 새 zip 을 그 이름으로 바꿈.
+//하나라도 실패하면 원래 zip 은 그대로 둠. jxl 로 바꾼 이미지 수를 돌려줌.
+fn repack_zip(
+    zip_path: &Path,
+    cjxl_args: &JxlArgs,
+    encoder: &dyn Encoder,
+    decoder: Option<&dyn Decoder>,
+    report: &Report,
+) -> Result<usize, Box<dyn Error>> {
+    let extension = zip_path
+        .extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_string();
+    let temp_dir = zip_path.with_extension(format!("{}.repack", extension));
+    //지난번에 중간에 꺼졌으면 남아있음.
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir)?;
+    }
+
+    let mut archive = ZipArchive::new(File::open(zip_path)?)?;
+    let result =
+        extract_images(&mut archive, zip_path, &temp_dir, cjxl_args, report).and_then(|images| {
+            let converted = convert_images(&images, cjxl_args, encoder, decoder)?;
+            if converted.is_empty() {
+                return Ok(0);
+            }
+            let temp_path = zip_path.with_extension(format!("{}.part", extension));
+            let partial = PartialFile::new(&temp_path);
+            let result = write_repacked_zip(&mut archive, &temp_path, &converted)
+                .and_then(|packed_entries| check_packed_zip(&temp_path, &packed_entries));
+            if let Err(err) = result {
+                let _ = fs::remove_file(&temp_path);
+                return Err(err);
+            }
+
+            //원래 zip 을 옆에 둔 뒤 새 zip 으로 바꾸고, 그 다음에 원래 zip 을 치움.
+            //중간에 꺼져도 원래 zip 이나 .orig 중 하나는 남아있음.
+            let orig_path = zip_path.with_extension(format!("{}.orig", extension));
+            //하드 링크를 쓰면 zip_path 가 비는 순간이 없음. 안 되는 파일 시스템이면 이름을 바꿈.
+            if fs::hard_link(zip_path, &orig_path).is_err() {
+                fs::rename(zip_path, &orig_path)?;
+            }
+            fs::rename(&temp_path, zip_path)?;
+            drop(partial);
+            sync_parent_dir(zip_path)?;
+            //zip 과 같은 방법으로 원래 zip 을 치움.
+            if let Err(err) = delete_path(&orig_path, cjxl_args) {
+                warn!(
+                    "Failed to delete the original zip: {}\nerror message: {}",
+                    orig_path.display(),
+                    err
+                );
+            }
+            Ok(converted.len())
+        });
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir)?;
+    }
+    result
+}
+
+//확장자가 이미지인 항목을 임시 폴더에 "{순번}.{확장자}" 로 풂. zip 안의 경로는 쓰지 않음.
+//min_bytes, min_age_days, max_pixels 에 걸린 이미지는 그대로 두고 report 에 "{zip}/{이름}" 으로 남김.
+fn extract_images(
+    archive: &mut ZipArchive<File>,
+    zip_path: &Path,
+    temp_dir: &Path,
+    cjxl_args: &JxlArgs,
+    report: &Report,
+) -> Result<Vec<EntryImage>, Box<dyn Error>> {
+    //zip 에 있는 이름과 앞에서 jxl 로 바꾸기로 한 이름. 01.png 와 01.jpg 는 앞의 것만 바꿈.
+    let mut names: HashSet<String> = archive.file_names().map(str::to_string).collect();
+    let mut images = vec![];
+    for index in 0..archive.len() {
+        if interrupt::interrupted() {
+            return Err("interrupted".into());
+        }
+        let mut entry = archive.by_index(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let Some((stem, ext)) = name.rsplit_once('.') else {
+            continue;
+        };
+        let ext = ext.to_lowercase();
+        if SourceFormat::from_extension(&ext).is_none() {
+            continue;
+        }
+        let jxl_name = format!("{}.jxl", stem);
+        if names.contains(&jxl_name) {
+            warn!(
+                "{} is already in the zip or taken by another image, keep {}",
+                jxl_name, name
+            );
+            continue;
+        }
+
+        let size = entry.size();
+        let modified = metadata::zip_entry_modified(entry.extra_data(), entry.last_modified());
+        let skip = |reason: String| {
+            info!("Skip {} in {} ({})", name, zip_path.display(), reason);
+            report.skipped(&zip_path.join(&name), &reason, size);
+        };
+        if let Some(reason) = size_age_reason(cjxl_args, size, modified) {
+            skip(reason);
+            continue;
+        }
+
+        fs::create_dir_all(temp_dir)?;
+        let source_path = temp_dir.join(format!("{}.{}", index, ext));
+        io::copy(&mut entry, &mut File::create(&source_path)?)?;
+        if let Some(reason) = pixels_reason(cjxl_args, &source_path) {
+            skip(reason);
+            continue;
+        }
+        let image_format = ImageReader::open(&source_path)?
+            .with_guessed_format()?
+            .format()
+            .filter(|format| SourceFormat::from_image_format(*format).is_some());
+        let Some(image_format) = image_format else {
+            warn!("Not supported image, keep: {}", name);
+            continue;
+        };
+        names.insert(jxl_name.clone());
+        images.push(EntryImage {
+            index,
+            name,
+            jxl_name,
+            jxl_path: source_path.with_extension("jxl"),
+            source_path,
+            image_format,
+        });
+    }
+    Ok(images)
+}
+
+//jxl 로 바꿀 이미지만 순번으로 돌려줌. size_policy 로 원본을 남기는 이미지는 빠짐.
+fn convert_images<'a>(
+    images: &'a [EntryImage],
+    cjxl_args: &JxlArgs,
+    encoder: &dyn Encoder,
+    decoder: Option<&dyn Decoder>,
+) -> Result<HashMap<usize, &'a EntryImage>, Box<dyn Error>> {
+    let results: Vec<Result<bool, String>> = images
+        .par_iter()
+        .map(|image| {
+            if interrupt::interrupted() {
+                return Err("interrupted".to_string());
+            }
+            encoder.encode(
+                &image.source_path,
+                &image.jxl_path,
+                image.image_format,
+                cjxl_args,
+            )?;
+            let source_size = fs::metadata(&image.source_path)
+                .map_err(|err| err.to_string())?
+                .len();
+            let jxl_size = fs::metadata(&image.jxl_path)
+                .map_err(|err| err.to_string())?
+                .len();
+            if !cjxl_args
+                .size_policy
+                .keep_jxl(source_size, jxl_size, cjxl_args.min_savings)
+            {
+                return Ok(false);
+            }
+            if let (Some(format), Ok(source), Ok(jxl)) = (
+                SourceFormat::from_image_format(image.image_format),
+                fs::read(&image.source_path),
+                fs::read(&image.jxl_path),
+            ) {
+                let problems =
+                    metadata::check_metadata(&source, format, &jxl, cjxl_args.strip_for(format));
+                if !problems.is_empty() {
+                    warn!("Metadata of {}: {}", image.name, problems.join(", "));
+                }
+            }
+            if let Some(decoder) = decoder {
+                decoder::verify_jxl(
+                    decoder,
+                    cjxl_args,
+                    &image.source_path,
+                    &image.jxl_path,
+                    image.image_format,
+                )
+                .map_err(|err| format!("Verification failed: {}: {}", image.jxl_name, err))?;
+            }
+            Ok(true)
+        })
+        .collect();
+
+    let mut converted = HashMap::new();
+    for (image, result) in images.iter().zip(results) {
+        if result? {
+            converted.insert(image.index, image);
+        }
+    }
+    Ok(converted)
+}
+
+//원래 zip 의 순서대로 씀. 이미지는 jxl 로, 나머지는 압축된 그대로 옮김.
+fn write_repacked_zip(
+    archive: &mut ZipArchive<File>,
+    temp_path: &Path,
+    converted: &HashMap<usize, &EntryImage>,
+) -> Result<Vec<PackedEntry>, Box<dyn Error>> {
+    let mut zip = ZipWriter::new(File::create(temp_path)?);
+    let mut packed_entries = vec![];
+    for index in 0..archive.len() {
+        if interrupt::interrupted() {
+            return Err("interrupted".into());
+        }
+        match converted.get(&index) {
+            Some(image) => {
+                let entry = archive.by_index_raw(index)?;
+                let mut zip_options = FileOptions::default()
+                    .compression_method(Stored)
+                    .last_modified_time(entry.last_modified());
+                if let Some(mode) = entry.unix_mode() {
+                    zip_options = zip_options.unix_permissions(mode);
+                }
+                let modified =
+                    metadata::zip_entry_modified(entry.extra_data(), entry.last_modified());
+                drop(entry);
+                let data = fs::read(&image.jxl_path)?;
+                let packed = PackedEntry::new(image.jxl_name.clone(), &data);
+                start_file_with_times(
+                    &mut zip,
+                    &image.jxl_name,
+                    zip_options,
+                    modified,
+                    None,
+                    packed.xxh3,
+                )?;
+                zip.write_all(&data)?;
+                packed_entries.push(packed);
+            }
+            None => {
+                //확인할 때 비교하려고 풀어서 한 번 읽음.
+                let mut data = vec![];
+                archive.by_index(index)?.read_to_end(&mut data)?;
+                let entry = archive.by_index_raw(index)?;
+                packed_entries.push(PackedEntry::new(entry.name().to_string(), &data));
+                zip.raw_copy_file(entry)?;
+            }
+        }
+    }
+    let file = zip.finish()?;
+    file.sync_all()?;
+    Ok(packed_entries)
+}
@@ -0,0 +1,318 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+//--report 로 쓰는 결과. 원본 이미지 하나에 한 줄, 그 뒤에 폴더별 합계와 전체 합계.
+#[derive(Default)]
+pub struct Report {
+    files: Mutex<BTreeMap<PathBuf, ReportRow>>,
+}
+
+//csv 는 열이 모두 같아야 하므로 파일, 폴더, 전체를 한 모양으로 씀. 해당 없는 칸은 비워둠.
+#[derive(Serialize, Clone, Default)]
+pub struct ReportRow {
+    pub record: RecordKind,
+    pub path: PathBuf,
+    pub format: Option<&'static str>,
+    pub args: Option<String>,
+    pub outcome: Option<Outcome>,
+    pub input_bytes: u64,
+    //zip 에 들어간 크기. 원본을 남겼으면 원본 크기.
+    pub output_bytes: Option<u64>,
+    pub saved_bytes: Option<i64>,
+    pub ratio: Option<f64>,
+    pub encode_ms: Option<u64>,
+    pub verification: Option<Verification>,
+    //원본에서 빠진 메타데이터나 strip 하지 못한 메타데이터.
+    pub metadata: Option<String>,
+    pub archive: Option<PathBuf>,
+    pub deletion: Option<Deletion>,
+    //변환하지 않고 건너뛴 이유.
+    pub skip_reason: Option<String>,
+    //폴더, 전체 합계에만 씀.
+    pub files: Option<usize>,
+    pub failed: Option<usize>,
+    pub skipped: Option<usize>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    #[default]
+    File,
+    Folder,
+    Run,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Converted,
+    //지난번에 변환을 마친 jxl 을 그대로 씀.
+    Resumed,
+    //jxl 대신 원본을 zip 에 넣음.
+    SourceKept,
+    Failed,
+    //필터에 걸려 변환하지 않음. 원본은 그대로 남음.
+    Skipped,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    NotRun,
+    Passed,
+    Failed,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Deletion {
+    Kept,
+    Trashed,
+    Deleted,
+}
+
+impl Report {
+    //변환을 시작할 때 한 줄을 만듦. 나머지 칸은 update 로 채움.
+    pub fn start(&self, path: &Path, format: &'static str, args: &[String], input_bytes: u64) {
+        self.files.lock().unwrap().insert(
+            path.to_path_buf(),
+            ReportRow {
+                path: path.to_path_buf(),
+                format: Some(format),
+                args: Some(args.join(" ")),
+                input_bytes,
+                verification: Some(Verification::NotRun),
+                deletion: Some(Deletion::Kept),
+                ..Default::default()
+            },
+        );
+    }
+
+    pub fn update(&self, path: &Path, update: impl FnOnce(&mut ReportRow)) {
+        if let Some(row) = self.files.lock().unwrap().get_mut(path) {
+            update(row);
+        }
+    }
+
+    pub fn failed(&self, path: &Path, err: &str) {
+        self.update(path, |row| {
+            row.outcome = Some(Outcome::Failed);
+            row.output_bytes = None;
+            row.error = Some(err.to_string());
+        });
+    }
+
+    //변환하지 않고 건너뛴 원본. 합계의 files 와 크기에는 넣지 않음.
+    pub fn skipped(&self, path: &Path, reason: &str, input_bytes: u64) {
+        self.files.lock().unwrap().insert(
+            path.to_path_buf(),
+            ReportRow {
+                path: path.to_path_buf(),
+                outcome: Some(Outcome::Skipped),
+                input_bytes,
+                deletion: Some(Deletion::Kept),
+                skip_reason: Some(reason.to_string()),
+                ..Default::default()
+            },
+        );
+    }
+
+    //zip 에 들어간 원본.
+    pub fn packed(&self, sources: &[PathBuf], archive: &Path) {
+        for source in sources {
+            self.update(source, |row| {
+                row.archive = Some(archive.to_path_buf());
+            });
+        }
+    }
+
+    //파일 줄 뒤에 폴더별 합계, 마지막에 전체 합계.
+    fn rows(&self) -> Vec<ReportRow> {
+        let files = self.files.lock().unwrap();
+        let mut rows: Vec<ReportRow> = files
+            .values()
+            .cloned()
+            .map(|mut row| {
+                if let Some(output_bytes) = row.output_bytes {
+                    row.saved_bytes = Some(row.input_bytes as i64 - output_bytes as i64);
+                    row.ratio = ratio(output_bytes, row.input_bytes);
+                }
+                row
+            })
+            .collect();
+
+        let mut folders: BTreeMap<PathBuf, ReportRow> = BTreeMap::new();
+        let mut run = summary_row(RecordKind::Run, PathBuf::new());
+        for row in &rows {
+            let folder = row.path.parent().unwrap_or(Path::new("")).to_path_buf();
+            let folder_row = folders
+                .entry(folder.clone())
+                .or_insert_with(|| summary_row(RecordKind::Folder, folder));
+            add_to_summary(folder_row, row);
+            add_to_summary(&mut run, row);
+        }
+        for mut summary in folders.into_values().chain([run]) {
+            let output_bytes = summary.output_bytes.unwrap_or_default();
+            //합계의 ratio 는 실패한 파일을 빼고 계산함. 성공한 파일의 입력 크기 = 출력 + 절약.
+            let succeeded_input = output_bytes as i64 + summary.saved_bytes.unwrap_or_default();
+            summary.ratio = ratio(output_bytes, succeeded_input as u64);
+            rows.push(summary);
+        }
+        rows
+    }
+
+    //확장자가 csv 면 csv, 아니면 json lines.
+    pub fn write(&self, report_path: &Path) -> Result<(), Box<dyn Error>> {
+        let rows = self.rows();
+        let file = File::create(report_path)?;
+        if report_path.extension().and_then(OsStr::to_str) == Some("csv") {
+            let mut writer = csv::Writer::from_writer(file);
+            for row in &rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        } else {
+            let mut writer = BufWriter::new(file);
+            for row in &rows {
+                serde_json::to_writer(&mut writer, row)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn summary_row(record: RecordKind, path: PathBuf) -> ReportRow {
+    ReportRow {
+        record,
+        path,
+        output_bytes: Some(0),
+        saved_bytes: Some(0),
+        files: Some(0),
+        failed: Some(0),
+        skipped: Some(0),
+        ..Default::default()
+    }
+}
+
+fn add_to_summary(summary: &mut ReportRow, row: &ReportRow) {
+    if row.outcome == Some(Outcome::Skipped) {
+        *summary.skipped.get_or_insert(0) += 1;
+        return;
+    }
+    summary.input_bytes += row.input_bytes;
+    *summary.files.get_or_insert(0) += 1;
+    match row.output_bytes {
+        Some(output_bytes) => {
+            *summary.output_bytes.get_or_insert(0) += output_bytes;
+            *summary.saved_bytes.get_or_insert(0) += row.input_bytes as i64 - output_bytes as i64;
+        }
+        None => *summary.failed.get_or_insert(0) += 1,
+    }
+}
+
+fn ratio(output_bytes: u64, input_bytes: u64) -> Option<f64> {
+    (input_bytes > 0).then(|| output_bytes as f64 / input_bytes as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn converted(report: &Report, path: &str, input_bytes: u64, output_bytes: u64) {
+        report.start(
+            Path::new(path),
+            "png",
+            &["-d".to_string(), "0".to_string()],
+            input_bytes,
+        );
+        report.update(Path::new(path), |row| {
+            row.outcome = Some(Outcome::Converted);
+            row.output_bytes = Some(output_bytes);
+        });
+    }
+
+    fn find<'a>(rows: &'a [ReportRow], record: RecordKind, path: &str) -> &'a ReportRow {
+        rows.iter()
+            .find(|row| row.record == record && row.path == Path::new(path))
+            .unwrap()
+    }
+
+    #[test]
+    fn file_rows_get_saved_bytes_and_ratio() {
+        let report = Report::default();
+        converted(&report, "w/a/1.png", 100, 40);
+        converted(&report, "w/a/2.png", 100, 120);
+
+        let rows = report.rows();
+        let row = find(&rows, RecordKind::File, "w/a/1.png");
+        assert_eq!(row.args.as_deref(), Some("-d 0"));
+        assert_eq!(row.saved_bytes, Some(60));
+        assert_eq!(row.ratio, Some(0.4));
+        let row = find(&rows, RecordKind::File, "w/a/2.png");
+        assert_eq!(row.saved_bytes, Some(-20));
+    }
+
+    #[test]
+    fn summaries_group_by_folder_and_leave_out_failed_and_skipped_bytes() {
+        let report = Report::default();
+        converted(&report, "w/a/1.png", 100, 40);
+        converted(&report, "w/a/2.png", 200, 60);
+        converted(&report, "w/b/1.png", 100, 100);
+        report.start(Path::new("w/b/2.png"), "png", &[], 300);
+        report.failed(Path::new("w/b/2.png"), "broken");
+        report.skipped(Path::new("w/b/3.png"), "exclude_files \"3*\"", 500);
+
+        let rows = report.rows();
+        //파일 줄 5개, 폴더 2개, 전체 1개.
+        assert_eq!(rows.len(), 8);
+        assert!(rows[..5].iter().all(|row| row.record == RecordKind::File));
+        assert!(rows[7].record == RecordKind::Run);
+
+        let a = find(&rows, RecordKind::Folder, "w/a");
+        assert_eq!((a.files, a.failed, a.skipped), (Some(2), Some(0), Some(0)));
+        assert_eq!(
+            (a.input_bytes, a.output_bytes, a.saved_bytes),
+            (300, Some(100), Some(200))
+        );
+        assert_eq!(a.ratio, Some(100.0 / 300.0));
+
+        let b = find(&rows, RecordKind::Folder, "w/b");
+        assert_eq!((b.files, b.failed, b.skipped), (Some(2), Some(1), Some(1)));
+        //실패한 파일의 입력 크기는 input_bytes 에만 들어가고 ratio 에서는 빠짐.
+        assert_eq!(
+            (b.input_bytes, b.output_bytes, b.saved_bytes),
+            (400, Some(100), Some(0))
+        );
+        assert_eq!(b.ratio, Some(1.0));
+
+        let run = &rows[7];
+        assert_eq!(
+            (run.files, run.failed, run.skipped),
+            (Some(4), Some(1), Some(1))
+        );
+        assert_eq!(
+            (run.input_bytes, run.output_bytes, run.saved_bytes),
+            (700, Some(200), Some(200))
+        );
+        assert_eq!(run.ratio, Some(0.5));
+    }
+
+    #[test]
+    fn empty_report_has_only_run_summary() {
+        let rows = Report::default().rows();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].record == RecordKind::Run);
+        assert_eq!(rows[0].files, Some(0));
+        assert_eq!(rows[0].ratio, None);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::interrupt;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+//모든 폴더의 파일을 하나의 rayon 풀에서 변환하고, 폴더의 마지막 파일이 끝나면 그 폴더의 finish 를 부름.
+//파일이 적은 폴더가 있어도 다른 폴더의 파일이 남은 코어를 씀.
+//nested 면 하위 폴더의 finish 가 모두 끝난 뒤에 폴더의 finish 를 부름. 폴더를 지우거나 하위 폴더를 묶을 때.
+//finish 가 에러를 내면 아직 시작하지 않은 파일은 변환하지 않고 에러를 돌려줌.
+//Ctrl-C 를 누르면 새 파일을 시작하지 않고, 돌고 있는 변환만 기다림. finish 도 더 부르지 않음.
+pub fn run<T, R>(
+    folders: &[PathBuf],
+    files: Vec<Vec<T>>,
+    nested: bool,
+    convert: impl Fn(&T) -> R + Sync,
+    mut finish: impl FnMut(usize, Vec<R>) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>>
+where
+    T: Sync,
+    R: Send,
+{
+    let results: Vec<Mutex<Vec<(usize, R)>>> = files
+        .iter()
+        .map(|files| Mutex::new(Vec::with_capacity(files.len())))
+        .collect();
+    let remaining: Vec<AtomicUsize> = files
+        .iter()
+        .map(|files| AtomicUsize::new(files.len()))
+        .collect();
+    //폴더 순서대로 넣으므로 하위 폴더의 파일이 먼저 풀에 들어감.
+    let tasks: Vec<(usize, usize, &T)> = files
+        .iter()
+        .enumerate()
+        .flat_map(|(folder, files)| {
+            files
+                .iter()
+                .enumerate()
+                .map(move |(index, file)| (folder, index, file))
+        })
+        .collect();
+
+    //폴더마다 목록 안에 있는 상위 폴더.
+    let folder_index: HashMap<&Path, usize> = folders
+        .iter()
+        .enumerate()
+        .map(|(index, folder)| (folder.as_path(), index))
+        .collect();
+    let ancestors: Vec<Vec<usize>> = folders
+        .iter()
+        .map(|folder| {
+            if !nested {
+                return vec![];
+            }
+            folder
+                .ancestors()
+                .skip(1)
+                .filter_map(|ancestor| folder_index.get(ancestor).copied())
+                .collect()
+        })
+        .collect();
+    let mut unfinished_descendants = vec![0; folders.len()];
+    for &ancestor in ancestors.iter().flatten() {
+        unfinished_descendants[ancestor] += 1;
+    }
+
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    //파일이 없는 폴더는 바로 끝남.
+    for (folder, files) in files.iter().enumerate() {
+        if files.is_empty() {
+            sender.send(folder)?;
+        }
+    }
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            tasks
+                .par_iter()
+                .for_each_with(sender, |sender, &(folder, index, file)| {
+                    if stop.load(Ordering::Relaxed) || interrupt::interrupted() {
+                        return;
+                    }
+                    let result = convert(file);
+                    results[folder].lock().unwrap().push((index, result));
+                    if remaining[folder].fetch_sub(1, Ordering::AcqRel) == 1 {
+                        //받는 쪽이 먼저 끝났으면 보낼 필요 없음.
+                        let _ = sender.send(folder);
+                    }
+                });
+        });
+
+        let mut ready = vec![false; folders.len()];
+        let mut finished = vec![false; folders.len()];
+        //모든 파일이 끝나면 보내는 쪽이 모두 사라져서 반복이 끝남.
+        for folder in receiver {
+            //변환이 끝난 파일은 작업 내역에 남아 있으므로 다음에 이어서 zip 을 만듦.
+            if interrupt::interrupted() {
+                continue;
+            }
+            ready[folder] = true;
+            let mut candidates = vec![folder];
+            while let Some(folder) = candidates.pop() {
+                if !ready[folder] || finished[folder] || unfinished_descendants[folder] > 0 {
+                    continue;
+                }
+                finished[folder] = true;
+                let mut folder_results = mem::take(&mut *results[folder].lock().unwrap());
+                //변환이 끝난 순서가 아니라 폴더에서 읽은 순서로 넘김.
+                folder_results.sort_by_key(|(index, _)| *index);
+                let folder_results = folder_results.into_iter().map(|(_, result)| result);
+                if let Err(err) = finish(folder, folder_results.collect()) {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+                for &ancestor in &ancestors[folder] {
+                    unfinished_descendants[ancestor] -= 1;
+                    candidates.push(ancestor);
+                }
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,706 @@
+use crate::format::SourceFormat;
+use crate::metadata::MetadataKind;
+use crate::{JxlArgs, SizePolicy, XxHashMap};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use xxhash_rust::xxh3::xxh3_64;
+
+//작업 내역 파일. {hex}.bin 은 마지막으로 저장한 전체 내역, {hex}.journal 은 그 뒤에 바뀐 내용.
+fn worklist_stem(work_folder: &Path) -> String {
+    hex::encode(xxh3_64(work_folder.to_string_lossy().as_bytes()).to_be_bytes())
+}
+
+pub fn snapshot_path(work_folder: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.bin", worklist_stem(work_folder)))
+}
+
+fn journal_path(work_folder: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.journal", worklist_stem(work_folder)))
+}
+
+//파일 하나가 어디까지 처리됐는지. 순서대로 진행됨.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum FileStatus {
+    //변환을 시작함. 이 상태로 남은 jxl 은 쓰다 만 파일.
+    Pending,
+    Converted,
+    Verified,
+    //jxl 이 충분히 작지 않아서 지우고 원본을 남김.
+    SourceKept,
+    SourceDeleted,
+    Packed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileState {
+    pub status: FileStatus,
+    //원본이 바뀌었는지 확인하는 xxh3.
+    pub source_hash: u64,
+    pub jxl_path: PathBuf,
+    pub output_size: u64,
+    //변환할 때 쓴 EncodeSetting 의 id.
+    pub setting_id: u64,
+}
+
+impl FileState {
+    //같은 원본과 설정으로 jxl 까지 만들어 둔 파일. 다시 변환하지 않아도 됨.
+    pub fn is_converted(&self, source_hash: u64, setting_id: u64, jxl_path: &Path) -> bool {
+        self.status >= FileStatus::Converted
+            && self.status != FileStatus::SourceKept
+            && self.source_hash == source_hash
+            && self.setting_id == setting_id
+            && self.jxl_path == jxl_path
+            && jxl_path.is_file()
+    }
+
+    //같은 원본과 설정으로 원본을 남기기로 한 파일.
+    pub fn is_source_kept(&self, source_hash: u64, setting_id: u64) -> bool {
+        self.status == FileStatus::SourceKept
+            && self.source_hash == source_hash
+            && self.setting_id == setting_id
+    }
+}
+
+//결과 jxl 에 영향을 주는 설정. 이것이 바뀌면 예전 결과는 오래된 것으로 봄.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct EncodeSetting {
+    pub encoder_version: String,
+    pub format_args: BTreeMap<SourceFormat, Vec<String>>,
+    pub format_strip: BTreeMap<SourceFormat, Vec<MetadataKind>>,
+    pub size_policy: SizePolicy,
+    pub min_savings: f64,
+}
+
+impl EncodeSetting {
+    pub fn new(cjxl_args: &JxlArgs, encoder_version: &str) -> Self {
+        EncodeSetting {
+            encoder_version: encoder_version.to_string(),
+            format_args: cjxl_args.format_args.clone(),
+            format_strip: cjxl_args.format_strip.clone(),
+            size_policy: cjxl_args.size_policy,
+            min_savings: cjxl_args.min_savings,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        xxh3_64(&bincode::serialize(self).expect("Failed to serialize setting"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WorkInfo {
+    pub work_folder_path: String,
+    pub work_setting: JxlArgs,
+    //이 작업 내역을 만든 cjxl --version.
+    pub encoder_version: String,
+    pub worklist: XxHashMap<PathBuf, bool>,
+    //원본 이미지 경로별 상태.
+    pub files: XxHashMap<PathBuf, FileState>,
+    //지금까지 쓴 설정들. id 는 EncodeSetting::id.
+    pub settings: BTreeMap<u64, EncodeSetting>,
+    //끝난 폴더를 만든 설정의 id. 변환한 이미지가 없던 폴더는 없음.
+    pub folder_settings: XxHashMap<PathBuf, u64>,
+    //.jxlzip.toml 때문에 작업 폴더와 설정이 다른 폴더의 설정.
+    pub folder_args: XxHashMap<PathBuf, JxlArgs>,
+    //폴더로 만든 zip. --reencode-stale 로 다시 만들면 이 zip 을 바꿈.
+    pub archives: XxHashMap<PathBuf, PathBuf>,
+    //이번 실행에서 --reencode-stale 로 다시 변환하는 폴더. 저장하지 않음.
+    #[serde(skip)]
+    pub reencoded: HashSet<PathBuf>,
+}
+
+impl WorkInfo {
+    fn new(work_folder_path: String, work_setting: JxlArgs, encoder_version: String) -> Self {
+        WorkInfo {
+            work_folder_path,
+            work_setting,
+            encoder_version,
+            worklist: XxHashMap::default(),
+            files: XxHashMap::default(),
+            settings: BTreeMap::new(),
+            folder_settings: XxHashMap::default(),
+            folder_args: XxHashMap::default(),
+            archives: XxHashMap::default(),
+            reencoded: HashSet::new(),
+        }
+    }
+
+    //끝났지만 지금과 다른 설정으로 만든 폴더. current_setting_id 는 폴더의 지금 설정 id.
+    pub fn stale_folders(&self, current_setting_id: impl Fn(&Path) -> u64) -> Vec<PathBuf> {
+        let mut stale: Vec<PathBuf> = self
+            .worklist
+            .iter()
+            .filter(|(_, &done)| done)
+            .filter_map(|(path, _)| {
+                self.folder_settings
+                    .get(path)
+                    .filter(|&&id| id != current_setting_id(path))
+                    .map(|_| path.clone())
+            })
+            .collect();
+        stale.sort();
+        stale
+    }
+
+    //설정별로 끝난 폴더 수를 보여줌. 폴더 목록은 로그 파일에만 남김.
+    pub fn report_settings(&self, current_ids: &BTreeSet<u64>) {
+        let mut folders: BTreeMap<u64, Vec<&PathBuf>> = BTreeMap::new();
+        for (path, _) in self.worklist.iter().filter(|(_, &done)| done) {
+            if let Some(&id) = self.folder_settings.get(path) {
+                folders.entry(id).or_default().push(path);
+            }
+        }
+        for (id, mut paths) in folders {
+            paths.sort();
+            let text = match self.settings.get(&id) {
+                Some(setting) => {
+                    let mut text = format!(
+                        "setting {:016x}{}: {} folders\n    encoder: {}",
+                        id,
+                        if current_ids.contains(&id) {
+                            " (current)"
+                        } else {
+                            ""
+                        },
+                        paths.len(),
+                        setting.encoder_version
+                    );
+                    for (format, args) in &setting.format_args {
+                        text.push_str(&format!("\n    {}_args={:?}", format.config_key(), args));
+                    }
+                    text.push_str(&format!(
+                        "\n    size_policy={:?}, min_savings={}%",
+                        setting.size_policy, setting.min_savings
+                    ));
+                    text
+                }
+                None => format!("unknown setting {:016x}: {} folders", id, paths.len()),
+            };
+            info!("{}", text);
+            for path in paths {
+                debug!("    {}", path.display());
+            }
+        }
+    }
+
+    //저장된 내역에 journal 을 이어서 적용함. 내역이 없거나 읽을 수 없으면 None.
+    pub fn load(work_folder: &Path) -> Option<WorkInfo> {
+        WorkInfo::load_snapshot(work_folder).map(|(work_info, _)| work_info)
+    }
+
+    //두 번째 값은 예전 구조의 .bin 을 옮겨 온 것인지. 그러면 새 구조로 다시 저장해야 함.
+    fn load_snapshot(work_folder: &Path) -> Option<(WorkInfo, bool)> {
+        WorkInfo::read(&snapshot_path(work_folder), &journal_path(work_folder))
+    }
+
+    fn read(snapshot_path: &Path, journal_path: &Path) -> Option<(WorkInfo, bool)> {
+        let bytes = fs::read(snapshot_path).ok()?;
+        let (mut work_info, migrated) = match bincode::deserialize::<WorkInfo>(&bytes) {
+            Ok(work_info) => (work_info, false),
+            //zip 을 남기기 전의 구조. journal 은 지금과 같음.
+            Err(err) => match bincode::deserialize::<WorkInfoWithoutArchives>(&bytes) {
+                Ok(previous) => (previous.migrate(), true),
+                Err(_) => match bincode::deserialize::<LegacyWorkInfo>(&bytes) {
+                    Ok(legacy) => {
+                        info!(
+                            "Migrate worklist from the old format: {} folders done",
+                            legacy.worklist.values().filter(|&&done| done).count()
+                        );
+                        //처음 구조에는 journal 이 없었음.
+                        return Some((legacy.migrate(), true));
+                    }
+                    Err(_) => {
+                        warn!("Failed to load worklist: {}", err);
+                        return None;
+                    }
+                },
+            },
+        };
+
+        if let Ok(file) = File::open(journal_path) {
+            let mut reader = BufReader::new(file);
+            let mut replayed = 0;
+            loop {
+                match bincode::deserialize_from::<_, JournalEntry>(&mut reader) {
+                    Ok(entry) => {
+                        work_info.apply(entry);
+                        replayed += 1;
+                    }
+                    Err(err) => {
+                        //중간에 꺼져서 마지막 기록이 잘린 경우. 그 앞까지만 씀.
+                        if !matches!(*err, bincode::ErrorKind::Io(ref io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof)
+                        {
+                            warn!(
+                                "Worklist journal is broken after {} entries: {}",
+                                replayed, err
+                            );
+                        }
+                        break;
+                    }
+                }
+            }
+            if replayed > 0 {
+                info!("Resume from worklist journal: {} entries", replayed);
+            }
+        }
+        Some((work_info, migrated))
+    }
+
+    fn apply(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::FolderDone(folder_path, setting_id) => {
+                self.worklist.insert(folder_path.clone(), true);
+                match setting_id {
+                    Some(setting_id) => self.folder_settings.insert(folder_path, setting_id),
+                    None => self.folder_settings.remove(&folder_path),
+                };
+            }
+            JournalEntry::Setting(setting_id, setting) => {
+                self.settings.insert(setting_id, setting);
+            }
+            JournalEntry::File(image_path, state) => {
+                self.files.insert(image_path, state);
+            }
+            JournalEntry::FolderArgs(folder_path, Some(folder_args)) => {
+                self.folder_args.insert(folder_path, *folder_args);
+            }
+            JournalEntry::FolderArgs(folder_path, None) => {
+                self.folder_args.remove(&folder_path);
+            }
+            JournalEntry::Archive(folder_path, archive_path) => {
+                self.archives.insert(folder_path, archive_path);
+            }
+        }
+    }
+}
+
+//폴더로 만든 zip 을 남기기 전의 작업 내역.
+#[derive(Deserialize)]
+struct WorkInfoWithoutArchives {
+    work_folder_path: String,
+    work_setting: JxlArgs,
+    encoder_version: String,
+    worklist: XxHashMap<PathBuf, bool>,
+    files: XxHashMap<PathBuf, FileState>,
+    settings: BTreeMap<u64, EncodeSetting>,
+    folder_settings: XxHashMap<PathBuf, u64>,
+    folder_args: XxHashMap<PathBuf, JxlArgs>,
+}
+
+impl WorkInfoWithoutArchives {
+    //예전에 만든 zip 은 모르므로 다시 만들 때 번호를 붙인 새 zip 이 됨.
+    fn migrate(self) -> WorkInfo {
+        WorkInfo {
+            work_folder_path: self.work_folder_path,
+            work_setting: self.work_setting,
+            encoder_version: self.encoder_version,
+            worklist: self.worklist,
+            files: self.files,
+            settings: self.settings,
+            folder_settings: self.folder_settings,
+            folder_args: self.folder_args,
+            archives: XxHashMap::default(),
+            reencoded: HashSet::new(),
+        }
+    }
+}
+
+//처음 버전의 작업 내역. 폴더가 끝났는지만 남겼음.
+#[derive(Deserialize)]
+struct LegacyWorkInfo {
+    work_folder_path: String,
+    work_setting: LegacyJxlArgs,
+    worklist: XxHashMap<PathBuf, bool>,
+}
+
+#[derive(Deserialize)]
+struct LegacyJxlArgs {
+    delete_folder_plag: bool,
+    delete_source_image_plag: bool,
+    make_zip_plag: bool,
+    dont_use_trashcan_just_delete: bool,
+    png_args: Vec<String>,
+    jpg_args: Vec<String>,
+}
+
+impl LegacyWorkInfo {
+    //끝난 폴더는 설정 id 없이 끝난 것으로 옮김. 파일별 상태는 없으므로 남은 폴더는 처음부터 함.
+    fn migrate(self) -> WorkInfo {
+        let legacy = self.work_setting;
+        let mut work_setting = JxlArgs {
+            delete_folder_plag: legacy.delete_folder_plag,
+            delete_source_image_plag: legacy.delete_source_image_plag,
+            make_zip_plag: legacy.make_zip_plag,
+            dont_use_trashcan_just_delete: legacy.dont_use_trashcan_just_delete,
+            ..JxlArgs::default()
+        };
+        work_setting
+            .format_args
+            .insert(SourceFormat::Png, legacy.png_args);
+        work_setting
+            .format_args
+            .insert(SourceFormat::Jpeg, legacy.jpg_args);
+
+        let mut work_info =
+            WorkInfo::new(self.work_folder_path, work_setting, "unknown".to_string());
+        for (folder_path, done) in self.worklist {
+            if done {
+                work_info.apply(JournalEntry::FolderDone(folder_path, None));
+            }
+        }
+        work_info
+    }
+}
+
+//읽지 못한 작업 내역을 덮어쓰지 않도록 옆으로 옮겨 둠. 이미 있으면 번호를 붙임.
+fn back_up_unreadable(path: &Path) -> Result<(), io::Error> {
+    let mut backup_path = path.with_extension("bin.bak");
+    let mut number = 1;
+    while backup_path.exists() {
+        backup_path = path.with_extension(format!("bin.bak{}", number));
+        number += 1;
+    }
+    fs::rename(path, &backup_path)?;
+    warn!("Unreadable worklist is kept as {}", backup_path.display());
+    Ok(())
+}
+
+//journal 에 한 줄씩 붙이는 기록. 항상 최종 상태를 쓰므로 여러 번 적용해도 같음.
+#[derive(Serialize, Deserialize)]
+enum JournalEntry {
+    FolderDone(PathBuf, Option<u64>),
+    File(PathBuf, FileState),
+    Setting(u64, EncodeSetting),
+    FolderArgs(PathBuf, Option<Box<JxlArgs>>),
+    Archive(PathBuf, PathBuf),
+}
+
+//변환 중에 여러 스레드가 같이 쓰는 작업 내역. 바뀔 때마다 journal 에 바로 씀.
+pub struct Worklist {
+    work_folder: PathBuf,
+    //이번 실행의 인코더 버전. 폴더의 EncodeSetting id 를 만들 때 씀.
+    encoder_version: String,
+    work_info: Mutex<WorkInfo>,
+    journal: Mutex<File>,
+}
+
+impl Worklist {
+    pub fn open(
+        work_folder: &Path,
+        work_setting: &JxlArgs,
+        encoder_version: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (work_info, is_new) = match WorkInfo::load_snapshot(work_folder) {
+            Some((work_info, migrated)) => {
+                info!("Worklist file exists so load worklist.");
+                (work_info, migrated)
+            }
+            None => {
+                let snapshot_path = snapshot_path(work_folder);
+                if snapshot_path.exists() {
+                    back_up_unreadable(&snapshot_path)?;
+                }
+                info!("No worklist file so create new worklist.");
+                let work_info = WorkInfo::new(
+                    work_folder.to_string_lossy().to_string(),
+                    work_setting.clone(),
+                    encoder_version.to_string(),
+                );
+                (work_info, true)
+            }
+        };
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(journal_path(work_folder))?;
+        let worklist = Worklist {
+            work_folder: work_folder.to_path_buf(),
+            encoder_version: encoder_version.to_string(),
+            work_info: Mutex::new(work_info),
+            journal: Mutex::new(journal),
+        };
+        //journal 은 .bin 위에 적용하므로 새 내역과 옮겨 온 내역은 먼저 저장해 둠. 읽지 못한 예전 journal 도 비워짐.
+        if is_new {
+            worklist.save()?;
+        }
+        worklist.add_setting(work_setting)?;
+        //마지막으로 쓴 설정.
+        worklist.info().work_setting = work_setting.clone();
+        Ok(worklist)
+    }
+
+    //폴더 설정의 EncodeSetting id.
+    pub fn setting_id(&self, cjxl_args: &JxlArgs) -> u64 {
+        EncodeSetting::new(cjxl_args, &self.encoder_version).id()
+    }
+
+    //처음 보는 설정이면 작업 내역에 남기고 id 를 돌려줌.
+    pub fn add_setting(&self, cjxl_args: &JxlArgs) -> Result<u64, String> {
+        let setting = EncodeSetting::new(cjxl_args, &self.encoder_version);
+        let setting_id = setting.id();
+        if !self.info().settings.contains_key(&setting_id) {
+            self.record(JournalEntry::Setting(setting_id, setting))?;
+        }
+        Ok(setting_id)
+    }
+
+    //작업 폴더와 설정이 다른 폴더는 그 설정을 남기고, 같아지면 지움.
+    pub fn set_folder_args(&self, folder_path: &Path, cjxl_args: &JxlArgs) -> Result<(), String> {
+        let folder_args = {
+            let work_info = self.info();
+            let folder_args = (*cjxl_args != work_info.work_setting).then(|| cjxl_args.clone());
+            if work_info.folder_args.get(folder_path) == folder_args.as_ref() {
+                return Ok(());
+            }
+            folder_args
+        };
+        self.record(JournalEntry::FolderArgs(
+            folder_path.to_path_buf(),
+            folder_args.map(Box::new),
+        ))
+    }
+
+    pub fn info(&self) -> MutexGuard<'_, WorkInfo> {
+        self.work_info.lock().expect("worklist lock")
+    }
+
+    pub fn file(&self, image_path: &Path) -> Option<FileState> {
+        self.info().files.get(image_path).cloned()
+    }
+
+    pub fn set_file(&self, image_path: &Path, state: FileState) -> Result<(), String> {
+        self.record(JournalEntry::File(image_path.to_path_buf(), state))
+    }
+
+    pub fn set_status(&self, image_path: &Path, status: FileStatus) -> Result<(), String> {
+        match self.file(image_path) {
+            Some(state) => self.set_file(image_path, FileState { status, ..state }),
+            None => Ok(()),
+        }
+    }
+
+    //폴더로 만든 zip 을 남김.
+    pub fn set_archive(&self, folder_path: &Path, archive_path: &Path) -> Result<(), String> {
+        self.record(JournalEntry::Archive(
+            folder_path.to_path_buf(),
+            archive_path.to_path_buf(),
+        ))
+    }
+
+    //이번 실행에서 다시 변환하는 폴더면 지난번에 만든 zip.
+    pub fn reencoded_archive(&self, folder_path: &Path) -> Option<PathBuf> {
+        let work_info = self.info();
+        if !work_info.reencoded.contains(folder_path) {
+            return None;
+        }
+        work_info.archives.get(folder_path).cloned()
+    }
+
+    //setting_id 는 폴더를 만든 설정. None 은 변환한 이미지가 없는 폴더로, 설정이 바뀌어도 다시 할 일이 없음.
+    pub fn folder_done(&self, folder_path: &Path, setting_id: Option<u64>) -> Result<(), String> {
+        if !self.info().worklist.contains_key(folder_path) {
+            panic!("Failed to update worklist");
+        }
+        self.record(JournalEntry::FolderDone(
+            folder_path.to_path_buf(),
+            setting_id,
+        ))
+    }
+
+    fn record(&self, entry: JournalEntry) -> Result<(), String> {
+        let bytes = bincode::serialize(&entry).map_err(|err| err.to_string())?;
+        //한 번에 써서 프로그램이 죽어도 기록이 남도록 함. 잘린 기록은 읽을 때 버림.
+        self.journal
+            .lock()
+            .expect("journal lock")
+            .write_all(&bytes)
+            .map_err(|err| format!("Failed to write worklist journal: {}", err))?;
+        self.info().apply(entry);
+        Ok(())
+    }
+
+    //전원이 꺼져도 남도록 journal 을 디스크에 씀. 폴더 하나가 끝날 때마다 부름.
+    pub fn sync(&self) -> Result<(), io::Error> {
+        self.journal.lock().expect("journal lock").sync_data()
+    }
+
+    //전체 내역을 임시 파일에 쓰고 이름을 바꾼 뒤 journal 을 비움.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let snapshot_path = snapshot_path(&self.work_folder);
+        let temp_path = snapshot_path.with_extension("bin.part");
+        let mut file = File::create(&temp_path)?;
+        bincode::serialize_into(&mut file, &*self.info())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &snapshot_path)?;
+
+        let journal = self.journal.lock().expect("journal lock");
+        journal.set_len(0)?;
+        journal.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //테스트마다 따로 쓰는 임시 폴더.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("jxl_zip_maker_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_state(status: FileStatus) -> FileState {
+        FileState {
+            status,
+            source_hash: 1,
+            jxl_path: PathBuf::from("w/a/p.jxl"),
+            output_size: 10,
+            setting_id: 2,
+        }
+    }
+
+    fn entry_bytes(entry: &JournalEntry) -> Vec<u8> {
+        bincode::serialize(entry).unwrap()
+    }
+
+    #[test]
+    fn replays_journal_and_drops_torn_last_record() {
+        let dir = temp_dir("journal");
+        let snapshot = dir.join("w.bin");
+        let journal = dir.join("w.journal");
+        let work_info = WorkInfo::new("w".to_string(), JxlArgs::default(), "v1".to_string());
+        fs::write(&snapshot, bincode::serialize(&work_info).unwrap()).unwrap();
+
+        let image_path = PathBuf::from("w/a/p.png");
+        let mut bytes = vec![];
+        bytes.extend(entry_bytes(&JournalEntry::File(
+            image_path.clone(),
+            file_state(FileStatus::Pending),
+        )));
+        bytes.extend(entry_bytes(&JournalEntry::File(
+            image_path.clone(),
+            file_state(FileStatus::Converted),
+        )));
+        bytes.extend(entry_bytes(&JournalEntry::FolderDone(
+            PathBuf::from("w/a"),
+            Some(2),
+        )));
+        //마지막 기록은 쓰다가 꺼진 것처럼 자름.
+        let torn = entry_bytes(&JournalEntry::File(
+            image_path.clone(),
+            file_state(FileStatus::Packed),
+        ));
+        bytes.extend(&torn[..torn.len() - 3]);
+        fs::write(&journal, bytes).unwrap();
+
+        let (work_info, migrated) = WorkInfo::read(&snapshot, &journal).unwrap();
+        assert!(!migrated);
+        assert_eq!(work_info.files[&image_path].status, FileStatus::Converted);
+        assert_eq!(work_info.worklist.get(Path::new("w/a")), Some(&true));
+        assert_eq!(work_info.folder_settings.get(Path::new("w/a")), Some(&2));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_legacy_work_info() {
+        let dir = temp_dir("legacy");
+        let snapshot = dir.join("w.bin");
+        let mut worklist: XxHashMap<PathBuf, bool> = XxHashMap::default();
+        worklist.insert(PathBuf::from("w/done"), true);
+        worklist.insert(PathBuf::from("w/left"), false);
+        //bincode 는 구조체를 필드를 차례로 쓴 튜플과 같게 씀.
+        let legacy = (
+            "w".to_string(),
+            (
+                true,
+                false,
+                true,
+                false,
+                vec!["--distance=0".to_string()],
+                vec!["--lossless_jpeg=1".to_string()],
+            ),
+            worklist,
+        );
+        fs::write(&snapshot, bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let (work_info, migrated) = WorkInfo::read(&snapshot, &dir.join("w.journal")).unwrap();
+        assert!(migrated);
+        assert_eq!(work_info.encoder_version, "unknown");
+        assert!(work_info.work_setting.delete_folder_plag);
+        assert!(!work_info.work_setting.delete_source_image_plag);
+        assert_eq!(
+            work_info.work_setting.format_args[&SourceFormat::Png],
+            vec!["--distance=0".to_string()]
+        );
+        //끝나지 않은 폴더는 옮기지 않으므로 처음부터 함.
+        assert_eq!(work_info.worklist.len(), 1);
+        assert_eq!(work_info.worklist.get(Path::new("w/done")), Some(&true));
+        assert!(work_info.folder_settings.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_work_info_without_archives_and_replays_journal() {
+        let dir = temp_dir("archives");
+        let snapshot = dir.join("w.bin");
+        let journal = dir.join("w.journal");
+        let work_info = WorkInfo::new("w".to_string(), JxlArgs::default(), "v1".to_string());
+        let previous = (
+            &work_info.work_folder_path,
+            &work_info.work_setting,
+            &work_info.encoder_version,
+            &work_info.worklist,
+            &work_info.files,
+            &work_info.settings,
+            &work_info.folder_settings,
+            &work_info.folder_args,
+        );
+        fs::write(&snapshot, bincode::serialize(&previous).unwrap()).unwrap();
+        fs::write(
+            &journal,
+            entry_bytes(&JournalEntry::FolderDone(PathBuf::from("w/a"), None)),
+        )
+        .unwrap();
+
+        let (work_info, migrated) = WorkInfo::read(&snapshot, &journal).unwrap();
+        assert!(migrated);
+        assert_eq!(work_info.encoder_version, "v1");
+        assert!(work_info.archives.is_empty());
+        assert_eq!(work_info.worklist.get(Path::new("w/a")), Some(&true));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_snapshot_is_not_loaded() {
+        let dir = temp_dir("garbage");
+        let snapshot = dir.join("w.bin");
+        fs::write(&snapshot, b"not a worklist").unwrap();
+        assert!(WorkInfo::read(&snapshot, &dir.join("w.journal")).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backs_up_unreadable_snapshot_with_number() {
+        let dir = temp_dir("backup");
+        let snapshot = dir.join("w.bin");
+        fs::write(&snapshot, b"first").unwrap();
+        back_up_unreadable(&snapshot).unwrap();
+        fs::write(&snapshot, b"second").unwrap();
+        back_up_unreadable(&snapshot).unwrap();
+
+        assert!(!snapshot.exists());
+        assert_eq!(fs::read(dir.join("w.bin.bak")).unwrap(), b"first");
+        assert_eq!(fs::read(dir.join("w.bin.bak1")).unwrap(), b"second");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}